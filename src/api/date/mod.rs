@@ -0,0 +1,140 @@
+use crate::App;
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use tsuki::context::{Arg, Args, Context, Ret};
+use tsuki::{FromStr, Lua, Module, Ref, Table, fp};
+
+/// Implementation of [Module] for `date` API.
+pub struct DateModule;
+
+impl Module<App> for DateModule {
+    const NAME: &str = "date";
+
+    type Inst<'a> = Ref<'a, Table<App>>;
+
+    fn open(self, lua: &Lua<App>) -> Result<Self::Inst<'_>, Box<dyn core::error::Error>> {
+        let m = lua.create_table();
+
+        m.set_str_key("format", fp!(format));
+        m.set_str_key("now", fp!(now));
+        m.set_str_key("offset", fp!(offset));
+        m.set_str_key("parse", fp!(parse));
+
+        Ok(m)
+    }
+}
+
+fn format(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get arguments.
+    let time = to_time(&cx.arg(1))?;
+    let fmt = cx.arg(2);
+    let fmt = fmt
+        .to_str()?
+        .as_utf8()
+        .ok_or_else(|| fmt.error("expect UTF-8 string"))
+        .and_then(|v| StrftimeItems::new(v).parse().map_err(|e| fmt.error(e)))?;
+    let zone = to_zone(&cx.arg(3))?;
+
+    // Format.
+    let r = match zone {
+        Zone::Local => time
+            .with_timezone(&Local)
+            .format_with_items(fmt.iter())
+            .to_string(),
+        Zone::Utc => time.format_with_items(fmt.iter()).to_string(),
+    };
+
+    cx.push_str(r)?;
+
+    Ok(cx.into())
+}
+
+fn now(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    cx.push(Utc::now().timestamp())?;
+
+    Ok(cx.into())
+}
+
+fn offset(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    let time = match cx.arg(1).is_exists() {
+        true => to_time(&cx.arg(1))?,
+        false => Utc::now(),
+    };
+
+    let off = Local
+        .offset_from_utc_datetime(&time.naive_utc())
+        .fix()
+        .local_minus_utc();
+
+    cx.push(off)?;
+
+    Ok(cx.into())
+}
+
+fn parse(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get arguments.
+    let arg = cx.arg(1);
+    let str = arg
+        .to_str()?
+        .as_utf8()
+        .ok_or_else(|| arg.error("expect UTF-8 string"))?;
+    let fmt = cx.arg(2);
+    let fmt = fmt
+        .to_str()?
+        .as_utf8()
+        .ok_or_else(|| fmt.error("expect UTF-8 string"))?;
+    let zone = to_zone(&cx.arg(3))?;
+
+    // Parse with time zone from the string first.
+    if let Ok(v) = DateTime::parse_from_str(str, fmt) {
+        cx.push(v.timestamp())?;
+        return Ok(cx.into());
+    }
+
+    // Parse without time zone.
+    let time = match NaiveDateTime::parse_from_str(str, fmt) {
+        Ok(v) => v,
+        Err(e) => match NaiveDate::parse_from_str(str, fmt) {
+            Ok(v) => v.and_time(NaiveTime::MIN),
+            Err(_) => return Err(arg.error(e)),
+        },
+    };
+
+    let time = match zone {
+        Zone::Local => Local
+            .from_local_datetime(&time)
+            .earliest()
+            .ok_or_else(|| arg.error("the time does not exist on local time zone"))?
+            .timestamp(),
+        Zone::Utc => time.and_utc().timestamp(),
+    };
+
+    cx.push(time)?;
+
+    Ok(cx.into())
+}
+
+fn to_time(arg: &Arg<App>) -> Result<DateTime<Utc>, Box<dyn std::error::Error>> {
+    DateTime::from_timestamp(arg.to_int()?, 0).ok_or_else(|| arg.error("value out of range"))
+}
+
+fn to_zone(arg: &Arg<App>) -> Result<Zone, Box<dyn std::error::Error>> {
+    let zone = match arg.to_nilable_str(false)? {
+        Some(v) => v
+            .as_utf8()
+            .ok_or_else(|| arg.error("expect UTF-8 string"))?
+            .parse()
+            .map_err(|e| arg.error(e))?,
+        None => Zone::default(),
+    };
+
+    Ok(zone)
+}
+
+/// Time zone to use for `date` API.
+#[derive(Default, FromStr)]
+enum Zone {
+    #[default]
+    Local,
+    Utc,
+}
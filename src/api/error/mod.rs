@@ -0,0 +1,92 @@
+use super::os::ProcessFailed;
+use crate::App;
+use erdp::ErrorDisplay;
+use tsuki::context::{Args, Context, Ret};
+use tsuki::{CallError, Lua, Ref, UserData, fp};
+
+/// Class of the error value returned from `try`.
+pub struct ErrorObject(String);
+
+impl ErrorObject {
+    pub fn register(lua: &Lua<App>) {
+        let mt = lua.create_table();
+
+        mt.set_str_key("__tostring", fp!(Self::tostring));
+
+        lua.register_metatable::<Self>(&mt);
+    }
+
+    pub fn new<'a, T>(cx: &Context<'a, App, T>, e: &CallError) -> Ref<'a, UserData<App, Self>> {
+        // Get message.
+        let msg = e.display().to_string();
+        let ud = cx.create_ud(Self(match e.location() {
+            Some((f, l)) => format!("{f}:{l}: {msg}"),
+            None => msg.clone(),
+        }));
+
+        ud.set("message", cx.create_str(msg));
+
+        // Set location.
+        if let Some((f, l)) = e.location() {
+            ud.set("chunk", cx.create_str(f));
+            ud.set("line", l);
+        }
+
+        // Set kind.
+        let reason = e.reason();
+        let kind = if let Some(e) = reason.downcast_ref::<ProcessFailed>() {
+            let args = cx.create_table();
+
+            for (i, v) in e.args().iter().enumerate() {
+                args.set(i64::try_from(i + 1).unwrap(), cx.create_str(v.as_str()))
+                    .unwrap();
+            }
+
+            ud.set("program", cx.create_str(e.prog()));
+            ud.set("args", args);
+
+            if let Some(v) = e.status().code() {
+                ud.set("status", v);
+            }
+
+            if let Some(v) = e.signal() {
+                ud.set("signal", v);
+            }
+
+            if let Some(v) = e.stderr() {
+                ud.set("stderr", cx.create_bytes(v));
+            }
+
+            "process"
+        } else if Self::is_io(reason) {
+            "io"
+        } else {
+            "runtime"
+        };
+
+        ud.set("kind", cx.create_str(kind));
+
+        ud
+    }
+
+    fn is_io(mut e: &(dyn std::error::Error + 'static)) -> bool {
+        loop {
+            if e.is::<std::io::Error>() {
+                break true;
+            }
+
+            e = match e.source() {
+                Some(v) => v,
+                None => break false,
+            };
+        }
+    }
+
+    fn tostring(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+        let ud = cx.arg(1).get_ud::<Self>()?;
+
+        cx.push_str(ud.value().0.as_str())?;
+
+        Ok(cx.into())
+    }
+}
@@ -0,0 +1,182 @@
+use super::Exit;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::rc::{Rc, Weak};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// Keeps track of the interrupt signal (e.g. SIGINT from Ctrl-C) that Project received.
+///
+/// This also keeps track of `exit` that was called by a detached task, which interrupt the script
+/// the same way except the signal will not be forwarded.
+#[derive(Default)]
+pub struct Interrupt {
+    sig: Cell<Option<i32>>,
+    exit: Cell<Option<u8>>,
+    notify: Notify,
+    forwarders: RefCell<Vec<Forwarder>>,
+}
+
+impl Interrupt {
+    /// Returns the signal that interrupted Project, if any.
+    pub fn signal(&self) -> Option<i32> {
+        self.sig.get()
+    }
+
+    /// Returns exit code if a detached task called `exit`.
+    pub fn exited(&self) -> Option<u8> {
+        self.exit.get()
+    }
+
+    /// Returns [Err] with either [Interrupted] or [Exit] if Project was interrupted or a detached
+    /// task called `exit`.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(v) = self.sig.get() {
+            Err(Box::new(Interrupted(v)))
+        } else if let Some(v) = self.exit.get() {
+            Err(Box::new(Exit(v)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Same as [Self::check()] but also check the signal that has not been delivered by the
+    /// runtime.
+    ///
+    /// This need to be used instead of [Self::check()] when the runtime is being blocked. Note that
+    /// this may raise the interrupt, which forward the signal to all registered processes.
+    pub fn check_blocking(&self) -> Result<(), Box<dyn Error>> {
+        #[cfg(unix)]
+        if self.sig.get().is_none() {
+            match RECEIVED.load(Ordering::Relaxed) {
+                0 => {}
+                v => self.raise(v),
+            }
+        }
+
+        self.check()
+    }
+
+    /// Wait until Project was interrupted.
+    ///
+    /// This does not return when a detached task called `exit`. Use [Self::cancelled()] if this is
+    /// not desired.
+    pub async fn wait(&self) -> Interrupted {
+        loop {
+            let notified = self.notify.notified();
+
+            if let Some(v) = self.sig.get() {
+                return Interrupted(v);
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Wait until [Self::check()] returns [Err] and returns the error.
+    pub async fn cancelled(&self) -> Box<dyn Error> {
+        loop {
+            let notified = self.notify.notified();
+
+            if let Err(e) = self.check() {
+                return e;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Run `f` until it completed, Project was interrupted or a detached task called `exit`.
+    pub async fn guard<F: Future>(&self, f: F) -> Result<F::Output, Box<dyn Error>> {
+        tokio::select! {
+            v = f => Ok(v),
+            e = self.cancelled() => Err(e),
+        }
+    }
+
+    /// Register `f` to forward the interrupt signal to a child process represented by `owner`.
+    ///
+    /// `f` will be unregistered when `owner` is dropped.
+    pub fn forward<T: 'static>(&self, owner: &Rc<T>, f: impl Fn(&T, i32) + 'static) {
+        let weak = Rc::downgrade(owner);
+        let owner: Weak<dyn Any> = weak.clone();
+        let f = move |sig| {
+            if let Some(v) = weak.upgrade() {
+                f(&v, sig);
+            }
+        };
+
+        // Remove dead forwarders.
+        let mut forwarders = self.forwarders.borrow_mut();
+
+        forwarders.retain(|v| v.0.strong_count() != 0);
+        forwarders.push((owner, Box::new(f)));
+    }
+
+    /// Interrupt the script with [Exit] when a detached task called `exit`.
+    ///
+    /// This does nothing if the script already interrupted.
+    pub fn raise_exit(&self, code: u8) {
+        if self.sig.get().is_some() || self.exit.get().is_some() {
+            return;
+        }
+
+        self.exit.set(Some(code));
+        self.notify.notify_waiters();
+    }
+
+    /// Mark Project as interrupted by `sig` and forward it to all registered processes.
+    ///
+    /// This does nothing if Project already interrupted.
+    pub fn raise(&self, sig: i32) {
+        if self.sig.get().is_some() {
+            return;
+        }
+
+        self.sig.set(Some(sig));
+        self.notify.notify_waiters();
+
+        // Forward the signal.
+        let forwarders = self.forwarders.borrow();
+
+        for (_, f) in forwarders.iter() {
+            f(sig);
+        }
+    }
+}
+
+/// Install a signal handler to record `sig` for [Interrupt::check_blocking()].
+#[cfg(unix)]
+pub fn record_signal(sig: i32) -> Result<(), std::io::Error> {
+    let f = move || {
+        RECEIVED
+            .compare_exchange(0, sig, Ordering::Relaxed, Ordering::Relaxed)
+            .ok();
+    };
+
+    // SAFETY: f is async-signal-safe.
+    unsafe { signal_hook_registry::register(sig, f).map(|_| ()) }
+}
+
+/// Error when Project was interrupted by a signal.
+///
+/// This error cannot be caught by `try`.
+#[derive(Debug, Clone, Error)]
+#[error("interrupted by signal {0}")]
+pub struct Interrupted(i32);
+
+impl Interrupted {
+    pub fn signal(&self) -> i32 {
+        self.0
+    }
+}
+
+/// Owner of the process and a function to forward the signal to it.
+type Forwarder = (Weak<dyn Any>, Box<dyn Fn(i32)>);
+
+/// The first interrupt signal that was received by the handler installed with [record_signal()].
+#[cfg(unix)]
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
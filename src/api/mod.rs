@@ -1,20 +1,34 @@
 pub use self::args::ArgsModule;
+pub use self::date::DateModule;
+#[cfg(unix)]
+pub use self::interrupt::record_signal;
+pub use self::interrupt::{Interrupt, Interrupted};
 pub use self::json::JsonModule;
-pub use self::os::OsModule;
+pub use self::os::{OsModule, Reaper};
 pub use self::path::PathModule;
 pub use self::string::StringModule;
+pub use self::task::TaskModule;
+pub use self::time::TimeModule;
 pub use self::url::UrlModule;
 
+use self::error::ErrorObject;
 use crate::App;
+use erdp::ErrorDisplay;
 use std::fmt::{Display, Formatter};
-use tsuki::context::{Args, Context, Ret};
-use tsuki::{Lua, Module, Table, fp};
+use std::time::Duration;
+use tsuki::context::{Arg, Args, Context, Ret};
+use tsuki::{CallError, Lua, Module, Table, Value, fp};
 
 mod args;
+mod date;
+mod error;
+mod interrupt;
 mod json;
 mod os;
 mod path;
 mod string;
+mod task;
+mod time;
 mod url;
 
 /// Implementation of [Module] for global APIs.
@@ -32,6 +46,129 @@ impl GlobalModule {
 
         Err(Box::new(Exit(code)))
     }
+
+    async fn r#try(
+        cx: Context<'_, App, Args>,
+    ) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+        // Get function and its arguments.
+        let f = cx.arg(1);
+        let f = f
+            .as_lua_fn()
+            .ok_or_else(|| f.invalid_type("Lua function"))?;
+        let mut args = Vec::with_capacity(cx.args().saturating_sub(1));
+
+        for i in 2..=cx.args() {
+            args.push(Value::from_arg(&cx.arg(i)).unwrap());
+        }
+
+        // Invoke the function on a new thread so it can call async functions.
+        let td = cx.create_thread();
+        let e = match td.async_call::<Vec<Value<App>>>(f, args).await {
+            Ok(r) => {
+                cx.push(true)?;
+
+                for v in r {
+                    cx.push(v)?;
+                }
+
+                return Ok(cx.into());
+            }
+            Err(e) => e,
+        };
+
+        let e = catchable(e)?;
+
+        cx.push(false)?;
+        cx.push(ErrorObject::new(&cx, &e))?;
+
+        Ok(cx.into())
+    }
+
+    async fn retry(
+        cx: Context<'_, App, Args>,
+    ) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+        // Get options.
+        let opts = cx.arg(1);
+        let (retries, backoff) = match Value::from_arg(&opts) {
+            Some(Value::Int(v)) => (Some(v), None),
+            Some(Value::Table(t)) => {
+                let retries = match t.get_str_key("retries") {
+                    Value::Nil => None,
+                    Value::Int(v) => Some(v),
+                    v => {
+                        let ty = cx.type_name(v);
+
+                        return Err(opts.error(format!("expect integer on 'retries', got {ty}")));
+                    }
+                };
+
+                let backoff = get_duration(&cx, &opts, "backoff", t.get_str_key("backoff"))?;
+
+                (retries, backoff)
+            }
+            _ => return Err(opts.invalid_type("integer or table")),
+        };
+
+        let retries = match retries {
+            Some(v) => {
+                u32::try_from(v).map_err(|_| opts.error("value out of range on 'retries'"))?
+            }
+            None => 3,
+        };
+
+        let mut backoff = backoff.unwrap_or(Duration::from_secs(1));
+
+        // Get function and its arguments.
+        let f = cx.arg(2);
+        let f = f
+            .as_lua_fn()
+            .ok_or_else(|| f.invalid_type("Lua function"))?;
+        let mut args = Vec::with_capacity(cx.args().saturating_sub(2));
+
+        for i in 3..=cx.args() {
+            args.push(Value::from_arg(&cx.arg(i)).unwrap());
+        }
+
+        // Invoke the function until it succeeds.
+        let interrupt = &cx.associated_data().interrupt;
+        let mut attempt = 1;
+
+        loop {
+            let td = cx.create_thread();
+            let e = match td.async_call::<Vec<Value<App>>>(f, args.clone()).await {
+                Ok(r) => {
+                    for v in r {
+                        cx.push(v)?;
+                    }
+
+                    return Ok(cx.into());
+                }
+                Err(e) => e,
+            };
+
+            let e = catchable(e)?;
+            let msg = match e.location() {
+                Some((f, l)) => format!("{f}:{l}: {}", e.display()),
+                None => e.display().to_string(),
+            };
+
+            if attempt > retries {
+                eprintln!("Attempt {attempt} failed: {msg}. Giving up.");
+                return Err(e);
+            }
+
+            // Wait before the next attempt.
+            eprintln!(
+                "Attempt {attempt} failed: {msg}. Retrying in {}s.",
+                backoff.as_secs_f64()
+            );
+
+            interrupt.guard(tokio::time::sleep(backoff)).await?;
+
+            attempt += 1;
+            backoff = backoff.saturating_mul(2);
+        }
+    }
 }
 
 impl Module<App> for GlobalModule {
@@ -40,6 +177,10 @@ impl Module<App> for GlobalModule {
     type Inst<'a> = &'a Table<App>;
 
     fn open(self, lua: &Lua<App>) -> Result<Self::Inst<'_>, Box<dyn core::error::Error>> {
+        // Register classes.
+        ErrorObject::register(lua);
+
+        // Set functions.
         let m = lua.global();
 
         m.set_str_key("assert", fp!(tsuki::builtin::base::assert));
@@ -54,18 +195,31 @@ impl Module<App> for GlobalModule {
         m.set_str_key("rawget", fp!(tsuki::builtin::base::rawget));
         m.set_str_key("rawlen", fp!(tsuki::builtin::base::rawlen));
         m.set_str_key("rawset", fp!(tsuki::builtin::base::rawset));
+        m.set_str_key("retry", fp!(Self::retry as async));
         m.set_str_key("select", fp!(tsuki::builtin::base::select));
         m.set_str_key("setmetatable", fp!(tsuki::builtin::base::setmetatable));
         m.set_str_key("tonumber", fp!(tsuki::builtin::base::tonumber));
         m.set_str_key("tostring", fp!(tsuki::builtin::base::tostring));
+        m.set_str_key("try", fp!(Self::r#try as async));
         m.set_str_key("type", fp!(tsuki::builtin::base::r#type));
 
         Ok(m)
     }
 }
 
+/// Returns `e` as [CallError] if it can be caught by the script.
+///
+/// Both exit and interrupt will never be caught.
+fn catchable(e: Box<dyn std::error::Error>) -> Result<Box<CallError>, Box<dyn std::error::Error>> {
+    match e.downcast::<CallError>() {
+        Ok(v) if !v.reason().is::<Exit>() && !v.reason().is::<Interrupted>() => Ok(v),
+        Ok(v) => Err(v),
+        Err(e) => Err(e),
+    }
+}
+
 /// Encapsulates exit code to exit our process.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Exit(u8);
 
 impl Exit {
@@ -82,6 +236,31 @@ impl Display for Exit {
     }
 }
 
+/// Get [Duration] from `v`, which is a number of seconds.
+///
+/// Returns [None] if `v` is `nil`. `name` is the name of `v` to use in the error message.
+fn get_duration(
+    cx: &Context<App, Args>,
+    arg: &Arg<App>,
+    name: &str,
+    v: Value<App>,
+) -> Result<Option<Duration>, Box<dyn std::error::Error>> {
+    let v = match v {
+        Value::Nil => return Ok(None),
+        Value::Int(v) => v as f64,
+        Value::Float(v) => f64::from(v),
+        v => {
+            let ty = cx.type_name(v);
+
+            return Err(arg.error(format!("expect number on '{name}', got {ty}")));
+        }
+    };
+
+    Duration::try_from_secs_f64(v)
+        .map(Some)
+        .map_err(|e| arg.error(erdp::wrap(format!("invalid '{name}'"), e)))
+}
+
 fn join_path(
     cx: &Context<App, Args>,
     mut f: impl FnMut(usize, &str) -> Result<(), Box<dyn std::error::Error>>,
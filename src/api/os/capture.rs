@@ -1,7 +1,8 @@
+use super::pty::Pty;
+use super::spec::{Output, Spec};
 use crate::App;
-use std::borrow::Cow;
-use std::process::Stdio;
-use tokio::process::Command;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tsuki::context::{Args, Context, Ret};
 use tsuki::{FromStr, Value};
 
@@ -9,115 +10,160 @@ pub async fn entry(
     cx: Context<'_, App, Args>,
 ) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
     // Get options.
-    let prog = cx.arg(1);
-    let opts = if let Some(s) = prog.as_str(true) {
-        Options {
-            prog: s
-                .as_utf8()
-                .ok_or_else(|| prog.error("expect UTF-8 string"))?
-                .into(),
-            from: From::default(),
-        }
-    } else if let Some(t) = prog.as_table() {
-        // From.
-        let from = match t.get_str_key("from") {
-            Value::Nil => From::default(),
-            Value::Str(s) => s
-                .as_utf8()
-                .ok_or_else(|| prog.error("expect UTF-8 string on 'from'"))?
-                .parse()
-                .map_err(|e| prog.error(e))?,
-            v => {
-                let ty = cx.type_name(v);
-
-                return Err(prog.error(format!("expect string on 'from', got {ty}")));
+    let mut spec = Spec::from_args(&cx)?;
+    let arg = cx.arg(1);
+    let (from, trim, tee, pty) = match arg.as_table() {
+        Some(t) => {
+            // From.
+            let from = match t.get_str_key("from") {
+                Value::Nil => From::default(),
+                Value::Str(s) => s
+                    .as_utf8()
+                    .ok_or_else(|| arg.error("expect UTF-8 string on 'from'"))?
+                    .parse()
+                    .map_err(|e| arg.error(e))?,
+                v => {
+                    let ty = cx.type_name(v);
+
+                    return Err(arg.error(format!("expect string on 'from', got {ty}")));
+                }
+            };
+
+            // The stream we capture is always a pipe so it can't be redirected at the same time.
+            let captured: &[&str] = match from {
+                From::Stdout => &["stdout"],
+                From::Stderr => &["stderr"],
+                From::Both => &["stdout", "stderr"],
+            };
+
+            for &n in captured {
+                if !t.get_str_key(n).is_nil() {
+                    return Err(arg.error(format!("'{n}' cannot be set when capturing it")));
+                }
             }
-        };
-
-        // Program.
-        let prog = match t.get(1) {
-            Value::Str(s) => s
-                .as_utf8()
-                .ok_or_else(|| prog.error("expect UTF-8 string at index 1"))?
-                .to_owned()
-                .into(),
-            v => {
-                let ty = cx.type_name(v);
-
-                return Err(prog.error(format!("expect string at index 1, got {ty}")));
+
+            // Trim.
+            let trim = match t.get_str_key("trim") {
+                Value::Nil | Value::True => true,
+                Value::False => false,
+                v => {
+                    let ty = cx.type_name(v);
+
+                    return Err(arg.error(format!("expect boolean on 'trim', got {ty}")));
+                }
+            };
+
+            // Tee.
+            let tee = match t.get_str_key("tee") {
+                Value::Nil | Value::False => false,
+                Value::True => true,
+                v => {
+                    let ty = cx.type_name(v);
+
+                    return Err(arg.error(format!("expect boolean on 'tee', got {ty}")));
+                }
+            };
+
+            // Pty.
+            let pty = Pty::from_table(&cx, &arg, t)?;
+
+            if pty.is_some() && !matches!(from, From::Stdout) {
+                return Err(arg.error("'from' must be 'stdout' when 'pty' is enabled"));
             }
-        };
 
-        Options { prog, from }
-    } else {
-        return Err(prog.invalid_type("string or table"));
+            (from, trim, tee, pty)
+        }
+        None => (From::default(), true, false, None),
     };
 
-    // Get arguments.
-    let mut cmd = Command::new(opts.prog.as_ref());
-
-    for i in 2..=cx.args() {
-        // Get argument.
-        let arg = cx.arg(i);
-        let val = match arg.to_nilable_str(true)? {
-            Some(v) => v,
-            None => continue,
-        };
-
-        // Check if UTF-8.
-        let val = val
-            .as_utf8()
-            .ok_or_else(|| arg.error("expect UTF-8 string"))?;
-
-        cmd.arg(val);
-    }
-
     // Setup streams.
-    cmd.stdin(Stdio::null());
+    spec.deny_pipe(&arg, "capture")?;
 
-    match opts.from {
-        From::Stdout => {
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::inherit());
-        }
-        From::Stderr => {
-            cmd.stdout(Stdio::inherit());
-            cmd.stderr(Stdio::piped());
-        }
+    match from {
+        From::Stdout => spec.stdout = Output::Pipe,
+        From::Stderr => spec.stderr = Output::Pipe,
         From::Both => {
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::piped());
+            spec.stdout = Output::Pipe;
+            spec.stderr = Output::Pipe;
         }
     }
 
-    // Run.
-    let mut r = cmd
-        .spawn()
-        .map_err(|e| erdp::wrap(format!("failed to spawn '{}'", opts.prog), e))?
-        .wait_with_output()
-        .await
-        .map_err(|e| erdp::wrap(format!("failed to wait '{}'", opts.prog), e))?;
+    // Run. Our copy of the pseudo-terminal need to be closed as soon as the process has been
+    // spawned otherwise we will never reach the end of its output.
+    let mut cmd = spec.command()?;
+    let pty = match pty {
+        Some(v) => Some(v.attach(&mut cmd)?),
+        None => None,
+    };
+    // The process on a pseudo-terminal is a leader of a new session, which also has a new process
+    // group so it will not receive SIGINT from our terminal.
+    let group = spec.group() || pty.is_some();
+
+    #[cfg(unix)]
+    if group && pty.is_none() {
+        cmd.process_group(0);
+    }
 
-    if !r.status.success() {
-        return Err(format!("'{}' exited with an error ({})", opts.prog, r.status).into());
+    let mut child = spec.start(&mut cmd)?;
+
+    drop(cmd);
+
+    let pid = child.id();
+    let feed = spec.feed(&mut child.stdin);
+    let relay = spec.relay(&mut child.stdout, &mut child.stderr);
+    let wait = async {
+        if let Some(mut pty) = pty {
+            let w: Pin<Box<dyn AsyncWrite>> = match tee {
+                true => Box::pin(tokio::io::stdout()),
+                false => Box::pin(tokio::io::sink()),
+            };
+            // We need to keep the pseudo-terminal open until the process exited otherwise it
+            // will receive SIGHUP.
+            let stdout = self::tee(Some(&mut pty), w);
+            let (_, _, stdout, status) = tokio::join!(feed, relay, stdout, child.wait());
+
+            Ok(std::process::Output {
+                status: status?,
+                stdout: stdout?,
+                stderr: Vec::new(),
+            })
+        } else if tee {
+            let stdout = self::tee(child.stdout.take(), tokio::io::stdout());
+            let stderr = self::tee(child.stderr.take(), tokio::io::stderr());
+            let (_, _, stdout, stderr, status) =
+                tokio::join!(feed, relay, stdout, stderr, child.wait());
+
+            Ok(std::process::Output {
+                status: status?,
+                stdout: stdout?,
+                stderr: stderr?,
+            })
+        } else {
+            tokio::join!(feed, relay, child.wait_with_output()).2
+        }
+    };
+    let mut r = spec
+        .wait(pid, group, wait)
+        .await?
+        .map_err(|e| erdp::wrap(format!("failed to wait '{}'", spec.prog), e))?;
+
+    if trim {
+        self::trim(&mut r.stdout);
+        self::trim(&mut r.stderr);
     }
 
     // Set result.
-    match opts.from {
-        From::Stdout => {
-            trim(&mut r.stdout);
+    let stderr = match from {
+        From::Stdout => None,
+        From::Stderr | From::Both => Some(r.stderr.as_slice()),
+    };
 
-            cx.push_bytes(r.stdout)?;
-        }
-        From::Stderr => {
-            trim(&mut r.stderr);
+    spec.check(r.status, stderr)?;
 
-            cx.push_bytes(r.stderr)?;
-        }
+    match from {
+        From::Stdout => cx.push_bytes(r.stdout)?,
+        From::Stderr => cx.push_bytes(r.stderr)?,
         From::Both => {
-            trim(&mut r.stdout);
-            trim(&mut r.stderr);
-
             // Create result table.
             let t = cx.create_table();
             let o = cx.create_bytes(r.stdout);
@@ -130,10 +176,37 @@ pub async fn entry(
         }
     }
 
+    spec.push_status(&cx, r.status)?;
+
     Ok(cx.into())
 }
 
-fn trim(v: &mut Vec<u8>) {
+/// Read all data from `r` and write it to `w` as soon as it is available.
+async fn tee(
+    r: Option<impl AsyncRead + Unpin>,
+    mut w: impl AsyncWrite + Unpin,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut r = match r {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+    let mut buf = Vec::new();
+
+    loop {
+        let off = buf.len();
+
+        if r.read_buf(&mut buf).await? == 0 {
+            break;
+        }
+
+        w.write_all(&buf[off..]).await?;
+        w.flush().await?;
+    }
+
+    Ok(buf)
+}
+
+pub fn trim(v: &mut Vec<u8>) {
     if v.last().is_some_and(|&b| b == b'\n') {
         v.pop();
     }
@@ -143,11 +216,6 @@ fn trim(v: &mut Vec<u8>) {
     }
 }
 
-struct Options<'a> {
-    prog: Cow<'a, str>,
-    from: From,
-}
-
 #[derive(Default, Clone, Copy, FromStr)]
 enum From {
     #[default]
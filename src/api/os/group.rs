@@ -0,0 +1,119 @@
+use super::spawn::{TERM, send};
+use super::spec::Spec;
+use super::{GRACE, create_status};
+use crate::App;
+use std::cell::Cell;
+use std::process::ExitStatus;
+use std::rc::Rc;
+use tokio::task::JoinSet;
+use tsuki::context::{Args, Context, Ret};
+
+pub async fn entry(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    // Get processes. Each process is on its own process group by default so we can terminate all of
+    // its descendants when the other process was failed.
+    let arg = cx.arg(1);
+    let procs = Spec::from_named(&cx, &arg, "group", |n, mut s, _| {
+        s.group.get_or_insert(true);
+        Ok((n, s))
+    })?;
+
+    // Spawn all processes. We need to know which process has been reaped so we don't send a signal
+    // to the PID that may already be reused.
+    let mut tasks = JoinSet::new();
+    let mut running = Vec::with_capacity(procs.len());
+
+    for (i, (_, spec)) in procs.iter().enumerate() {
+        let mut child = match spec.spawn() {
+            Ok(v) => v,
+            Err(e) => {
+                terminate(&running, tasks).await;
+                return Err(e);
+            }
+        };
+
+        let err = format!("failed to wait '{}'", spec.prog);
+        let pid = child.id();
+        let done = Rc::new(Cell::new(false));
+        let feed = spec.feed(&mut child.stdin);
+        let relay = spec.relay(&mut child.stdout, &mut child.stderr);
+        let wait = spec.wait(pid, spec.group(), {
+            let done = done.clone();
+
+            async move {
+                let r = tokio::join!(feed, relay, child.wait()).2;
+                done.set(true);
+                r
+            }
+        });
+
+        tasks.spawn_local(async move {
+            let r = wait.await?.map_err(|e| erdp::wrap(err, e))?;
+
+            Ok::<_, Box<dyn std::error::Error>>((i, r))
+        });
+
+        running.push(Running {
+            pid,
+            group: spec.group(),
+            done,
+        });
+    }
+
+    // Wait for all processes.
+    let mut statuses: Vec<Option<ExitStatus>> = vec![None; procs.len()];
+
+    while let Some(r) = tasks.join_next().await {
+        let r = match r {
+            Ok(v) => v,
+            Err(e) => Err(erdp::wrap("failed to join a process", e).into()),
+        };
+        let r = r.and_then(|(i, status)| {
+            procs[i].1.check(status, None)?;
+            statuses[i] = Some(status);
+            Ok(())
+        });
+
+        if let Err(e) = r {
+            terminate(&running, tasks).await;
+            return Err(e);
+        }
+    }
+
+    // Set result.
+    let t = cx.create_table();
+
+    for ((name, _), status) in procs.into_iter().zip(statuses) {
+        t.set_str_key(name, create_status(&cx, status.unwrap()));
+    }
+
+    cx.push(t)?;
+
+    Ok(cx.into())
+}
+
+/// Send SIGTERM to all processes that still running and kill the remaining after the grace period.
+async fn terminate<T: 'static>(procs: &[Running], mut tasks: JoinSet<T>) {
+    let signal = |sig| {
+        for p in procs.iter().filter(|p| !p.done.get()) {
+            send(None, p.pid, p.group, sig).ok();
+        }
+    };
+
+    signal(Some(TERM));
+
+    tokio::time::timeout(GRACE, async { while tasks.join_next().await.is_some() {} })
+        .await
+        .ok();
+
+    // The processes itself will also be killed when the tasks is dropped but not its descendants.
+    signal(None);
+}
+
+/// Process of `os.group` that may still running.
+struct Running {
+    pid: Option<u32>,
+    group: bool,
+    done: Rc<Cell<bool>>,
+}
@@ -1,13 +1,29 @@
+pub use self::reaper::Reaper;
+
 use crate::App;
-use tsuki::{FromStr, Lua, Module, Ref, Table, fp};
+use std::process::ExitStatus;
+use std::time::Duration;
+use thiserror::Error;
+use tsuki::context::Context;
+use tsuki::{FromStr, Lua, Module, Nil, Ref, Table, fp};
 
 mod capture;
 mod copyfile;
 mod copyfileas;
 mod createdir;
+mod group;
+mod pipeline;
+mod prefix;
+mod pty;
+mod reaper;
 mod removedir;
+mod requires;
 mod run;
 mod spawn;
+mod spec;
+mod supervise;
+mod usage;
+mod which;
 
 /// Implementation of [Module] for `os` API.
 pub struct OsModule;
@@ -20,7 +36,9 @@ impl Module<App> for OsModule {
     fn open(self, lua: &Lua<App>) -> Result<Self::Inst<'_>, Box<dyn core::error::Error>> {
         // Register classes.
         lua.register_class::<self::spawn::Process>();
+        lua.register_class::<self::spawn::InputStream>();
         lua.register_class::<self::spawn::OutputStream>();
+        lua.register_class::<self::supervise::Supervisor>();
 
         // We need to manually create the table instead of using OsLib so the linker don't keep the
         // functions we don't use.
@@ -55,17 +73,111 @@ impl Module<App> for OsModule {
         m.set_str_key("copyfile", fp!(self::copyfile::entry as async));
         m.set_str_key("copyfileas", fp!(self::copyfileas::entry as async));
         m.set_str_key("createdir", fp!(self::createdir::entry));
+        m.set_str_key("group", fp!(self::group::entry as async));
+        m.set_str_key("pipeline", fp!(self::pipeline::entry as async));
         m.set_str_key("removedir", fp!(self::removedir::entry));
+        m.set_str_key("requires", fp!(self::requires::entry));
         m.set_str_key("run", fp!(self::run::entry as async));
         m.set_str_key("spawn", fp!(self::spawn::entry));
+        m.set_str_key("supervise", fp!(self::supervise::entry));
+        m.set_str_key("which", fp!(self::which::entry));
 
         Ok(m)
     }
 }
 
+/// Default grace period for a process to exit after it received a signal.
+const GRACE: Duration = Duration::from_secs(5);
+
 #[derive(Default, FromStr)]
 enum CopyMode {
     #[default]
     Content,
     All,
 }
+
+/// Push exit code and the signal that terminated the process (if any) to `cx`.
+///
+/// The exit code will be `nil` if the process was terminated by a signal.
+fn push_status<T>(
+    cx: &Context<App, T>,
+    status: ExitStatus,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match status.code() {
+        Some(v) => cx.push(v)?,
+        None => cx.push(Nil)?,
+    }
+
+    #[cfg(unix)]
+    if let Some(v) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        cx.push(v)?;
+    }
+
+    Ok(())
+}
+
+/// Create a table contains `status` and `signal` fields from `status`.
+fn create_status<'a, T>(cx: &Context<'a, App, T>, status: ExitStatus) -> Ref<'a, Table<App>> {
+    let t = cx.create_table();
+
+    if let Some(v) = status.code() {
+        t.set_str_key("status", v);
+    }
+
+    #[cfg(unix)]
+    if let Some(v) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        t.set_str_key("signal", v);
+    }
+
+    t
+}
+
+/// Error when a process exited with non-zero status.
+#[derive(Debug, Error)]
+#[error("'{prog}' exited with an error ({status})")]
+pub struct ProcessFailed {
+    prog: String,
+    args: Vec<String>,
+    status: ExitStatus,
+    stderr: Option<Vec<u8>>,
+}
+
+impl ProcessFailed {
+    fn new(prog: String, args: Vec<String>, status: ExitStatus, stderr: Option<Vec<u8>>) -> Self {
+        Self {
+            prog,
+            args,
+            status,
+            stderr,
+        }
+    }
+
+    pub fn prog(&self) -> &str {
+        &self.prog
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Returns signal that terminated the process.
+    ///
+    /// This always returns [None] on Windows.
+    pub fn signal(&self) -> Option<i32> {
+        #[cfg(unix)]
+        let r = std::os::unix::process::ExitStatusExt::signal(&self.status);
+        #[cfg(not(unix))]
+        let r = None;
+
+        r
+    }
+
+    /// Returns captured stderr of the process, if any.
+    pub fn stderr(&self) -> Option<&[u8]> {
+        self.stderr.as_deref()
+    }
+}
@@ -0,0 +1,139 @@
+use super::create_status;
+use super::spec::{Output, Spec};
+use crate::App;
+use std::process::{ExitStatus, Stdio};
+use tokio::task::JoinSet;
+use tsuki::context::{Args, Context, Ret};
+
+pub async fn entry(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    // Get stages.
+    let mut stages = Vec::with_capacity(cx.args());
+
+    for i in 1..=cx.args() {
+        let arg = cx.arg(i);
+        let mut spec = Spec::from_arg(&cx, &arg)?;
+
+        spec.deny_pipe(&arg, "pipeline")?;
+
+        if matches!(spec.stderr, Output::Stdout) {
+            return Err(arg.error("'stdout' on 'stderr' is not supported by 'pipeline'"));
+        }
+
+        // The stdout will be connected to the next stage.
+        spec.stdout = Output::Pipe;
+
+        stages.push(spec);
+    }
+
+    if stages.is_empty() {
+        return Err("expect at least one stage".into());
+    }
+
+    // Spawn all stages. The stdout of each stage will be connected to stdin of the next stage.
+    let mut tasks = JoinSet::new();
+    let mut prev: Option<Stdio> = None;
+    let mut feed = None;
+    let last = stages.len() - 1;
+
+    for (i, spec) in stages.iter().enumerate() {
+        let mut cmd = spec.command()?;
+
+        if let Some(v) = prev.take() {
+            cmd.stdin(v);
+        }
+
+        cmd.stdout(Stdio::piped());
+
+        #[cfg(unix)]
+        if spec.group() {
+            cmd.process_group(0);
+        }
+
+        // Spawn.
+        let mut child = spec.start(&mut cmd)?;
+        let err = format!("failed to wait '{}'", spec.prog);
+        let pid = child.id();
+        let relay = spec.relay(&mut child.stdout, &mut child.stderr);
+
+        if i == 0 {
+            feed = Some(spec.feed(&mut child.stdin));
+        }
+
+        // Wait for the stage. The remaining stages will be killed when JoinSet is dropped.
+        if i == last {
+            let wait = spec.wait(pid, spec.group(), async {
+                tokio::join!(relay, child.wait_with_output()).1
+            });
+
+            tasks.spawn_local(async move {
+                let r = wait.await?.map_err(|e| erdp::wrap(err, e))?;
+
+                Ok((i, r.status, r.stdout))
+            });
+        } else {
+            let stdout = child.stdout.take().unwrap();
+
+            prev = Some(
+                stdout
+                    .try_into()
+                    .map_err(|e| erdp::wrap("failed to convert stdout to stdin", e))?,
+            );
+
+            let wait = spec.wait(pid, spec.group(), async move {
+                tokio::join!(relay, child.wait()).1
+            });
+
+            tasks.spawn_local(async move {
+                let r = wait.await?.map_err(|e| erdp::wrap(err, e))?;
+
+                Ok::<_, Box<dyn std::error::Error>>((i, r, Vec::new()))
+            });
+        }
+    }
+
+    // Wait for all stages.
+    let mut statuses: Vec<Option<ExitStatus>> = vec![None; stages.len()];
+    let mut output = Vec::new();
+    let wait = async {
+        while let Some(r) = tasks.join_next().await {
+            let (i, status, out) = r.map_err(|e| erdp::wrap("failed to join a stage", e))??;
+
+            statuses[i] = Some(status);
+
+            if i == last {
+                output = out;
+            }
+        }
+
+        Ok::<_, Box<dyn std::error::Error>>(())
+    };
+
+    match feed {
+        Some(f) => tokio::join!(f, wait).1?,
+        None => wait.await?,
+    }
+
+    let statuses: Vec<ExitStatus> = statuses.into_iter().map(Option::unwrap).collect();
+
+    // Check statuses. The rightmost failed stage will be reported the same as pipefail.
+    for (spec, &status) in stages.iter().zip(&statuses).rev() {
+        spec.check(status, None)?;
+    }
+
+    // Set results.
+    super::capture::trim(&mut output);
+
+    let t = cx.create_table();
+
+    for (i, status) in statuses.into_iter().enumerate() {
+        t.set(i64::try_from(i + 1).unwrap(), create_status(&cx, status))
+            .unwrap();
+    }
+
+    cx.push_bytes(output)?;
+    cx.push(t)?;
+
+    Ok(cx.into())
+}
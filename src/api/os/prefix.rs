@@ -0,0 +1,77 @@
+use std::io::{IsTerminal, Write};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/// Label to prepend to each line of the process output.
+#[derive(Clone)]
+pub struct Prefix {
+    label: String,
+    color: usize,
+}
+
+impl Prefix {
+    /// Create a new [Prefix] with a color derived from `label`.
+    pub fn new(label: String) -> Self {
+        let color = label
+            .bytes()
+            .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b.into()));
+
+        Self { label, color }
+    }
+
+    pub fn with_color(label: String, color: usize) -> Self {
+        Self { label, color }
+    }
+
+    /// Write each line from `r` to stdout or stderr of Project with the label prepended.
+    pub async fn relay(&self, r: Option<impl AsyncRead + Unpin>, stderr: bool) {
+        let mut r = match r {
+            Some(v) => BufReader::new(v),
+            None => return,
+        };
+
+        // Build the label.
+        let tty = match stderr {
+            true => std::io::stderr().is_terminal(),
+            false => std::io::stdout().is_terminal(),
+        };
+
+        let label = match tty {
+            true => format!(
+                "\x1b[{}m{} |\x1b[0m ",
+                COLORS[self.color % COLORS.len()],
+                self.label
+            ),
+            false => format!("{} | ", self.label),
+        };
+
+        // Relay.
+        let mut line = label.clone().into_bytes();
+
+        loop {
+            line.truncate(label.len());
+
+            match r.read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+
+            if line.last().is_none_or(|&b| b != b'\n') {
+                line.push(b'\n');
+            }
+
+            // Write the whole line at once so it does not interleave with the other processes. We
+            // need to keep reading on error otherwise the process will block when the pipe is full.
+            match stderr {
+                true => std::io::stderr().lock().write_all(&line).ok(),
+                false => {
+                    let mut w = std::io::stdout().lock();
+
+                    w.write_all(&line).and_then(|_| w.flush()).ok()
+                }
+            };
+        }
+    }
+}
+
+/// ANSI color codes for [Prefix].
+const COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
@@ -0,0 +1,296 @@
+use crate::App;
+use std::pin::Pin;
+use std::task::Poll;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::process::Command;
+use tsuki::Table;
+use tsuki::Value;
+use tsuki::context::{Arg, Args, Context};
+
+/// Pseudo-terminal options accepted by `os.spawn` and `os.capture`.
+pub struct Pty {
+    rows: u16,
+    cols: u16,
+}
+
+impl Pty {
+    /// Returns [None] if `pty` is not enabled.
+    pub fn from_table(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        t: &Table<App>,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match t.get_str_key("pty") {
+            Value::Nil | Value::False => return Ok(None),
+            Value::True => {}
+            v => {
+                let ty = cx.type_name(v);
+
+                return Err(arg.error(format!("expect boolean on 'pty', got {ty}")));
+            }
+        }
+
+        if cfg!(not(unix)) {
+            return Err(arg.error("'pty' is not supported on this platform"));
+        }
+
+        // The pseudo-terminal replaces both stdout and stderr so these options would be ignored.
+        for n in ["stdout", "stderr", "prefix"] {
+            if !t.get_str_key(n).is_nil() {
+                return Err(arg.error(format!("'{n}' cannot be used with 'pty'")));
+            }
+        }
+
+        // Get size.
+        let (rows, cols) = Self::default_size();
+        let rows = Self::size(cx, arg, t, "rows")?.unwrap_or(rows);
+        let cols = Self::size(cx, arg, t, "cols")?.unwrap_or(cols);
+
+        Ok(Some(Self { rows, cols }))
+    }
+
+    /// Open a pseudo-terminal and set it as stdout and stderr of `cmd`.
+    ///
+    /// The process will be a leader of a new session with the pseudo-terminal as its controlling
+    /// terminal. `cmd` must be dropped after the process has been spawned otherwise the returned
+    /// [PtyReader] will never reach the end of stream.
+    #[cfg(unix)]
+    pub fn attach(&self, cmd: &mut Command) -> Result<PtyReader, Box<dyn std::error::Error>> {
+        use std::ffi::CStr;
+        use std::io::Error;
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::os::unix::fs::OpenOptionsExt;
+        use tokio::io::unix::AsyncFd;
+
+        // Open master.
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+
+        if master < 0 {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to open pseudo-terminal", e).into());
+        }
+
+        let master = unsafe { OwnedFd::from_raw_fd(master) };
+        let fd = master.as_raw_fd();
+
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+            || unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) } < 0
+            || unsafe { libc::grantpt(fd) } < 0
+            || unsafe { libc::unlockpt(fd) } < 0
+        {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to setup pseudo-terminal", e).into());
+        }
+
+        // Set size.
+        let size = libc::winsize {
+            ws_row: self.rows,
+            ws_col: self.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) } < 0 {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to set pseudo-terminal size", e).into());
+        }
+
+        // Open slave. We are single-threaded so ptsname is safe here.
+        let path = unsafe { libc::ptsname(fd) };
+
+        if path.is_null() {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to get pseudo-terminal name", e).into());
+        }
+
+        let path = unsafe { CStr::from_ptr(path) };
+        let path = std::str::from_utf8(path.to_bytes()).unwrap();
+        let slave = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)
+            .map_err(|e| erdp::wrap(format!("failed to open {path}"), e))?;
+
+        // Use LF as EOL the same as pipe.
+        let mut attrs = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        if unsafe { libc::tcgetattr(slave.as_raw_fd(), &mut attrs) } < 0 {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to get pseudo-terminal attributes", e).into());
+        }
+
+        attrs.c_oflag &= !libc::ONLCR;
+
+        if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &attrs) } < 0 {
+            let e = Error::last_os_error();
+
+            return Err(erdp::wrap("failed to set pseudo-terminal attributes", e).into());
+        }
+
+        // Setup command. The closure will be run after stdout has been redirected.
+        let stdout = slave
+            .try_clone()
+            .map_err(|e| erdp::wrap("failed to duplicate pseudo-terminal", e))?;
+
+        cmd.stdout(stdout);
+        cmd.stderr(slave);
+
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(1, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(Error::last_os_error());
+                }
+
+                Ok(())
+            })
+        };
+
+        AsyncFd::new(master)
+            .map(PtyReader)
+            .map_err(|e| erdp::wrap("failed to register pseudo-terminal", e).into())
+    }
+
+    #[cfg(not(unix))]
+    pub fn attach(&self, _: &mut Command) -> Result<PtyReader, Box<dyn std::error::Error>> {
+        unreachable!()
+    }
+
+    /// Returns size of our terminal or 24x80 if stdout is not a terminal.
+    #[cfg(unix)]
+    fn default_size() -> (u16, u16) {
+        let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+
+        if unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } < 0
+            || size.ws_row == 0
+            || size.ws_col == 0
+        {
+            return (24, 80);
+        }
+
+        (size.ws_row, size.ws_col)
+    }
+
+    #[cfg(not(unix))]
+    fn default_size() -> (u16, u16) {
+        (24, 80)
+    }
+
+    fn size(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        t: &Table<App>,
+        key: &str,
+    ) -> Result<Option<u16>, Box<dyn std::error::Error>> {
+        match t.get_str_key(key) {
+            Value::Nil => Ok(None),
+            Value::Int(v) => u16::try_from(v)
+                .ok()
+                .filter(|&v| v != 0)
+                .map(Some)
+                .ok_or_else(|| arg.error(format!("value out of range on '{key}'"))),
+            v => {
+                let ty = cx.type_name(v);
+
+                Err(arg.error(format!("expect integer on '{key}', got {ty}")))
+            }
+        }
+    }
+}
+
+/// Master side of a pseudo-terminal.
+#[cfg(unix)]
+pub struct PtyReader(tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>);
+
+#[cfg(unix)]
+impl PtyReader {
+    /// Returns a handle to keep the pseudo-terminal open.
+    pub fn handle(&self) -> Result<PtyHandle, Box<dyn std::error::Error>> {
+        self.0
+            .get_ref()
+            .try_clone()
+            .map(PtyHandle)
+            .map_err(|e| erdp::wrap("failed to duplicate pseudo-terminal", e).into())
+    }
+}
+
+#[cfg(unix)]
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        use std::os::fd::AsRawFd;
+
+        loop {
+            let mut guard = std::task::ready!(self.0.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            let r = guard.try_io(|fd| {
+                let fd = fd.as_raw_fd();
+                let n = unsafe { libc::read(fd, unfilled.as_mut_ptr().cast(), unfilled.len()) };
+
+                if n < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+
+            match r {
+                Ok(Ok(n)) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                // The master will return EIO when all slaves has been closed.
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for PtyReader {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// Master side of a pseudo-terminal.
+#[cfg(not(unix))]
+pub struct PtyReader(std::convert::Infallible);
+
+#[cfg(not(unix))]
+impl PtyReader {
+    pub fn handle(&self) -> Result<PtyHandle, Box<dyn std::error::Error>> {
+        match self.0 {}
+    }
+}
+
+#[cfg(not(unix))]
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        _: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.0 {}
+    }
+}
+
+/// Keeps the pseudo-terminal open.
+///
+/// The process will receive SIGHUP when the pseudo-terminal is closed.
+#[cfg(unix)]
+pub struct PtyHandle(#[allow(dead_code)] std::os::fd::OwnedFd);
+
+/// Keeps the pseudo-terminal open.
+#[cfg(not(unix))]
+pub struct PtyHandle(#[allow(dead_code)] std::convert::Infallible);
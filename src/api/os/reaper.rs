@@ -0,0 +1,66 @@
+use std::cell::{Cell, RefCell};
+use tokio::task::JoinSet;
+
+/// Keeps track of the processes that are being terminated in the background.
+///
+/// The script must wait for [Reaper::join()] before exit otherwise those processes will be killed
+/// without a chance to exit gracefully.
+#[derive(Default)]
+pub struct Reaper {
+    tasks: RefCell<JoinSet<()>>,
+    hooks: RefCell<Vec<Hook>>,
+    closed: Cell<bool>,
+}
+
+impl Reaper {
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    /// Spawn `f` on the current [LocalSet](tokio::task::LocalSet).
+    ///
+    /// # Panics
+    /// If [Reaper::is_closed()] returns `true`.
+    pub fn spawn(&self, f: impl Future<Output = ()> + 'static) {
+        assert!(!self.closed.get());
+
+        self.tasks.borrow_mut().spawn_local(f);
+    }
+
+    /// Register `f` to be called when [Reaper::join()] is called.
+    ///
+    /// This allows `f` to spawn a task for the objects that are still alive when the script
+    /// finished.
+    ///
+    /// # Panics
+    /// If [Reaper::is_closed()] returns `true`.
+    pub fn defer(&self, f: impl FnOnce(&Self) + 'static) {
+        assert!(!self.closed.get());
+
+        self.hooks.borrow_mut().push(Box::new(f));
+    }
+
+    /// Wait for all spawned tasks to complete then close this reaper.
+    pub async fn join(&self) {
+        // Other tasks may spawn a new one while we are waiting so we can't hold the borrow.
+        loop {
+            let hooks = std::mem::take(&mut *self.hooks.borrow_mut());
+
+            for f in hooks {
+                f(self);
+            }
+
+            let mut tasks = std::mem::take(&mut *self.tasks.borrow_mut());
+
+            if tasks.is_empty() {
+                break;
+            }
+
+            while tasks.join_next().await.is_some() {}
+        }
+
+        self.closed.set(true);
+    }
+}
+
+type Hook = Box<dyn FnOnce(&Reaper)>;
@@ -0,0 +1,33 @@
+use super::which::find;
+use crate::App;
+use tsuki::context::{Args, Context, Ret};
+
+/// Implementation of `os.requires`.
+pub fn entry(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get arguments.
+    let name = cx.arg(1);
+    let name = name
+        .to_str()?
+        .as_utf8()
+        .ok_or_else(|| name.error("expect UTF-8 string"))?;
+    let hint = cx.arg(2);
+    let hint = match hint.to_nilable_str(false)? {
+        Some(v) => Some(
+            v.as_utf8()
+                .ok_or_else(|| hint.error("expect UTF-8 string"))?
+                .to_owned(),
+        ),
+        None => None,
+    };
+
+    // Search.
+    let path = match (find(name, None), hint) {
+        (Some(v), _) => v,
+        (None, Some(h)) => return Err(format!("{name} not found, {h}").into()),
+        (None, None) => return Err(format!("{name} not found").into()),
+    };
+
+    cx.push_str(path)?;
+
+    Ok(cx.into())
+}
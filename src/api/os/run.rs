@@ -1,47 +1,54 @@
+use super::spec::Spec;
 use crate::App;
-use std::process::Stdio;
-use tokio::process::Command;
+use tsuki::Value;
 use tsuki::context::{Args, Context, Ret};
 
 pub async fn entry(
     cx: Context<'_, App, Args>,
 ) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
     // Get options.
-    let prog = cx.arg(1);
-    let prog = prog
-        .get_str()?
-        .as_utf8()
-        .ok_or_else(|| prog.error("expect UTF-8 string"))?;
-
-    // Get arguments.
-    let mut cmd = Command::new(prog);
-
-    for i in 2..=cx.args() {
-        // Get argument.
-        let arg = cx.arg(i);
-        let val = match arg.to_nilable_str(true)? {
-            Some(v) => v,
-            None => continue,
-        };
-
-        // Check if UTF-8.
-        let val = val
-            .as_utf8()
-            .ok_or_else(|| arg.error("expect UTF-8 string"))?;
-
-        cmd.arg(val);
-    }
+    let spec = Spec::from_args(&cx)?;
+
+    let arg = cx.arg(1);
+    let stats = match arg.as_table().map(|t| t.get_str_key("stats")) {
+        None | Some(Value::Nil | Value::False) => false,
+        Some(Value::True) => true,
+        Some(v) => {
+            let ty = cx.type_name(v);
+
+            return Err(arg.error(format!("expect boolean on 'stats', got {ty}")));
+        }
+    };
 
-    cmd.stdin(Stdio::null());
+    spec.deny_pipe(&arg, "run")?;
 
     // Run.
-    let status = cmd
-        .status()
-        .await
-        .map_err(|e| erdp::wrap(format!("failed to run '{prog}'"), e))?;
+    let mut cmd = spec.command()?;
+
+    #[cfg(unix)]
+    if spec.group() {
+        cmd.process_group(0);
+    }
+
+    let mut child = spec.start_tracked(&mut cmd)?;
+
+    drop(cmd);
+
+    let pid = child.id();
+    let feed = spec.feed(&mut child.stdin);
+    let relay = spec.relay(&mut child.stdout, &mut child.stderr);
+    let wait = async { tokio::join!(feed, relay, child.wait()).2 };
+    let status = spec
+        .wait(pid, spec.group(), wait)
+        .await?
+        .map_err(|e| erdp::wrap(format!("failed to wait '{}'", spec.prog), e))?;
+
+    // Check status.
+    spec.check(status, None)?;
 
-    if !status.success() {
-        return Err(format!("'{prog}' exited with an error ({status})").into());
+    match stats {
+        true => cx.push(child.usage().unwrap().create_table(&cx, status))?,
+        false => spec.push_status(&cx, status)?,
     }
 
     Ok(cx.into())
@@ -1,33 +1,136 @@
+use super::pty::{Pty, PtyHandle};
+use super::reaper::Reaper;
+use super::spec::Spec;
+use super::usage::TrackedChild;
+use super::{GRACE, push_status};
 use crate::App;
+use crate::api::{Interrupt, get_duration};
 use memchr::memchr;
-use std::borrow::Cow;
 use std::cell::RefCell;
-use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
-use std::process::{Child, Command, Stdio};
-use tokio::io::{AsyncRead, AsyncReadExt};
-use tokio::process::ChildStdout;
+use std::process::ExitStatus;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::ChildStdin;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tsuki::context::{Args, Context, Ret};
-use tsuki::{FromStr, Ref, Table, Value, class};
+use tsuki::{Nil, Ref, UserData, Value, class, fp};
 
 /// Implementation of `os.spawn`.
 pub fn entry(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
-    // Spawn.
-    let mut prog = spawn(&cx)?;
+    // Get options.
+    let spec = Spec::from_args(&cx)?;
+    let arg = cx.arg(1);
+    let pty = match arg.as_table() {
+        Some(t) => Pty::from_table(&cx, &arg, t)?,
+        None => None,
+    };
+
+    // Spawn. The process will be a leader of a new process group so we can kill all of its
+    // descendants. The process on a pseudo-terminal always be a leader of a new session, which
+    // also has a new process group.
+    let mut cmd = spec.command()?;
+    let pty = match pty {
+        Some(v) => Some(v.attach(&mut cmd)?),
+        None => None,
+    };
+    let group = spec.group.unwrap_or(true) || pty.is_some();
+    let handle = match &pty {
+        Some(v) => Some(v.handle()?),
+        None => None,
+    };
+
+    #[cfg(unix)]
+    if group && pty.is_none() {
+        cmd.process_group(0);
+    }
+
+    let mut prog = spec.start_tracked(&mut cmd)?;
+
+    drop(cmd);
+
+    // Write input and relay outputs. This need to be done before taking the streams since they will
+    // take the streams they need. We need to keep track of them since OutputStream::lines() will
+    // block them.
+    let feed = spec.feed(&mut prog.stdin);
+    let relay = spec.relay(&mut prog.stdout, &mut prog.stderr);
+    let busy = match spec.feeding() || spec.relaying() {
+        true => Some(Rc::new(tokio::task::spawn_local(async move {
+            tokio::join!(feed, relay);
+        }))),
+        false => None,
+    };
+
+    let stdin = prog.stdin.take();
     let stdout = prog.stdout.take();
-    let prog = cx.create_ud(Process(RefCell::new(Some(prog))));
-
-    // Set stdout.
-    if let Some(v) = stdout {
-        let v = match ChildStdout::from_std(v) {
-            Ok(v) => cx.create_ud(OutputStream(RefCell::new(OutputState {
-                rdr: Some(Box::pin(v)),
-                buf: Vec::new(),
-            }))),
-            Err(e) => return Err(erdp::wrap("failed to convert stdout to asynchronous", e).into()),
-        };
+    let stderr = prog.stderr.take();
+
+    // Kill the process when timed out. If the process is being waited the waiter will kill it.
+    let pid = prog.id();
+    let prog = Rc::new(RefCell::new(prog));
+    let deadline = spec.timeout.map(|v| {
+        Rc::new(Deadline {
+            at: Instant::now() + v,
+            prog: Rc::downgrade(&prog),
+            pid,
+            group,
+        })
+    });
+
+    if let Some(v) = deadline.clone() {
+        tokio::task::spawn_local(async move {
+            tokio::time::sleep_until(v.at).await;
+            v.check();
+        });
+    }
 
-        prog.set("stdout", v);
+    // Forward interrupt signal. SIGINT from the terminal is already delivered to the process if it
+    // is on the same process group as us.
+    #[cfg(unix)]
+    cx.associated_data()
+        .interrupt
+        .forward(&prog, move |prog, sig| {
+            if group || sig != libc::SIGINT {
+                signal_child(prog, pid, group, Some(sig)).ok();
+            }
+        });
+
+    let prog = cx.create_ud(Process {
+        prog,
+        pid,
+        group,
+        deadline: deadline.as_ref().map(|v| v.at),
+        _pty: handle,
+        reaper: cx.associated_data().reaper.clone(),
+    });
+
+    if let Some(v) = pid {
+        prog.set("pid", v);
+    }
+
+    // Set streams.
+    if let Some(v) = stdin {
+        prog.set("stdin", cx.create_ud(InputStream(RefCell::new(Some(v)))));
+    }
+
+    let both = stdout.is_some() && stderr.is_some();
+
+    if let Some(v) = pty {
+        prog.set(
+            "stdout",
+            OutputStream::new(&cx, v, busy.clone(), deadline.clone(), both),
+        );
+    } else if let Some(v) = stdout {
+        prog.set(
+            "stdout",
+            OutputStream::new(&cx, v, busy.clone(), deadline.clone(), both),
+        );
+    }
+
+    if let Some(v) = stderr {
+        prog.set("stderr", OutputStream::new(&cx, v, busy, deadline, both));
     }
 
     cx.push(prog)?;
@@ -35,214 +138,416 @@ pub fn entry(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::e
     Ok(cx.into())
 }
 
-fn spawn(cx: &Context<App, Args>) -> Result<Child, Box<dyn std::error::Error>> {
-    // Get options.
-    let arg = cx.arg(1);
-    let opts = if let Some(prog) = arg.as_str(true) {
-        let prog = prog
-            .as_utf8()
-            .ok_or_else(|| arg.error("expect UTF-8 string"))?
-            .into();
-
-        Options {
-            prog,
-            cwd: None,
-            stdout: Stream::Inherit,
-            env: Env::Inherit,
+/// Class of the value that returned from `os.spawn`.
+pub struct Process {
+    prog: Rc<RefCell<TrackedChild>>,
+    pid: Option<u32>,
+    group: bool,
+    deadline: Option<Instant>,
+    _pty: Option<PtyHandle>,
+    reaper: Rc<Reaper>,
+}
+
+#[class(associated_data = App)]
+impl Process {
+    async fn wait(&self, cx: &Context<'_, App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut prog = self
+            .prog
+            .try_borrow_mut()
+            .map_err(|_| "the process is being waited by another thread")?;
+        let wait = async {
+            match self.deadline {
+                Some(v) => match tokio::time::timeout_at(v, prog.wait()).await {
+                    Ok(v) => v,
+                    Err(_) => {
+                        send(Some(&mut *prog), self.pid, self.group, None).ok();
+                        prog.wait().await
+                    }
+                },
+                None => prog.wait().await,
+            }
+        };
+
+        let status = cx.associated_data().interrupt.guard(wait).await?;
+
+        let status = status.map_err(|e| erdp::wrap("failed to wait the process", e))?;
+
+        push_status(cx, status)?;
+
+        Ok(())
+    }
+
+    fn try_wait(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        match self.try_status()? {
+            Some(v) => {
+                cx.push(true)?;
+                push_status(cx, v)?;
+            }
+            None => cx.push(false)?,
         }
-    } else if let Some(t) = arg.as_table() {
-        // Get program name.
-        let prog = match t.get(1) {
-            Value::Str(v) => v
-                .as_utf8()
-                .ok_or_else(|| arg.error("expect UTF-8 string at index #1"))?
-                .to_owned()
-                .into(),
-            v => {
-                return Err(arg.error(format!(
-                    "expect string at index #1, got {}",
-                    cx.type_name(v)
-                )));
+
+        Ok(())
+    }
+
+    fn status(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let v = match self.try_status()? {
+            Some(v) if v.code().is_some() => "exited",
+            Some(_) => "signaled",
+            None => "running",
+        };
+
+        cx.push_str(v)?;
+
+        Ok(())
+    }
+
+    async fn terminate(
+        &self,
+        cx: &Context<'_, App, Args>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Get options.
+        let arg = cx.arg(2);
+        let (sig, grace) = match Value::from_arg(&arg) {
+            None | Some(Value::Nil) => (None, None),
+            Some(Value::Table(t)) => {
+                let sig = match t.get_str_key("signal") {
+                    Value::Nil => None,
+                    Value::Int(v) => Some(
+                        i32::try_from(v).map_err(|_| arg.error("signal number out of range"))?,
+                    ),
+                    Value::Str(v) => {
+                        let v = v
+                            .as_utf8()
+                            .ok_or_else(|| arg.error("expect UTF-8 string on 'signal'"))?;
+
+                        Some(
+                            parse_signal(v)
+                                .ok_or_else(|| arg.error(format!("unknown signal '{v}'")))?,
+                        )
+                    }
+                    v => {
+                        let ty = cx.type_name(v);
+
+                        return Err(
+                            arg.error(format!("expect integer or string on 'signal', got {ty}"))
+                        );
+                    }
+                };
+
+                let grace = get_duration(cx, &arg, "grace", t.get_str_key("grace"))?;
+
+                (sig, grace)
+            }
+            Some(v) => {
+                return Err(arg.error(format!("expect table, got {}", cx.type_name(v))));
             }
         };
 
-        // Get cwd.
-        let cwd = match t.get_str_key("cwd") {
-            Value::Nil => None,
-            Value::Str(v) => v
-                .as_utf8()
-                .ok_or_else(|| arg.error("expect UTF-8 string on 'cwd'"))?
-                .to_owned()
-                .into(),
-            v => return Err(arg.error(format!("expect string on 'cwd', got {}", cx.type_name(v)))),
+        // Terminate.
+        let mut prog = self
+            .prog
+            .try_borrow_mut()
+            .map_err(|_| "the process is being waited by another thread")?;
+        let status = match prog.try_wait()? {
+            Some(v) => v,
+            None => {
+                let sig = sig.unwrap_or(TERM);
+                let grace = grace.unwrap_or(GRACE);
+
+                send(Some(&mut *prog), self.pid, self.group, Some(sig))?;
+
+                reap(&mut prog, self.pid, self.group, grace)
+                    .await
+                    .map_err(|e| erdp::wrap("failed to wait the process", e))?
+            }
         };
 
-        // Get stdout.
-        let stdout = match t.get_str_key("stdout") {
-            Value::Nil => Stream::Inherit,
-            Value::Str(v) => {
+        push_status(cx, status)?;
+
+        Ok(())
+    }
+
+    fn kill(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let arg = cx.arg(2);
+        let sig = match Value::from_arg(&arg) {
+            None | Some(Value::Nil) => None,
+            Some(Value::Int(v)) => i32::try_from(v)
+                .map(Some)
+                .map_err(|_| arg.error("signal number out of range"))?,
+            Some(Value::Str(v)) => {
                 let v = v
                     .as_utf8()
-                    .ok_or_else(|| arg.error("expect UTF-8 string on 'stdout'"))?;
+                    .ok_or_else(|| arg.error("expect UTF-8 string"))?;
 
-                v.parse()
-                    .map_err(|_| arg.error(format!("unknown option '{v}' on 'stdout'")))?
+                Some(parse_signal(v).ok_or_else(|| arg.error(format!("unknown signal '{v}'")))?)
             }
-            v => {
-                return Err(arg.error(format!(
-                    "expect string on 'stdout', got {}",
-                    cx.type_name(v)
-                )));
+            Some(v) => {
+                return Err(arg.error(format!("expect integer or string, got {}", cx.type_name(v))));
             }
         };
 
-        // Get env.
-        let env = match t.get_str_key("env") {
-            Value::Nil | Value::True => Env::Inherit,
-            Value::False => Env::Clear,
-            Value::Table(t) => Env::Update(t),
-            v => {
-                return Err(arg.error(format!(
-                    "expect boolean or table on 'env', got {}",
-                    cx.type_name(v)
-                )));
-            }
+        self.signal(sig)
+    }
+
+    fn stats(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        // The process is running if it is being waited.
+        let mut prog = match self.prog.try_borrow_mut() {
+            Ok(v) => v,
+            Err(_) => return Ok(cx.push(Nil)?),
         };
 
-        Options {
-            prog,
-            cwd,
-            stdout,
-            env,
+        let status = prog
+            .try_wait()
+            .map_err(|e| erdp::wrap("failed to get process status", e))?;
+
+        match (status, prog.usage()) {
+            (Some(s), Some(u)) => cx.push(u.create_table(cx, s))?,
+            _ => cx.push(Nil)?,
         }
-    } else {
-        return Err(arg.invalid_type("string or table"));
-    };
 
-    // Get arguments.
-    let mut cmd = Command::new(opts.prog.as_ref());
+        Ok(())
+    }
 
-    for i in 2..=cx.args() {
-        // Get argument.
-        let arg = cx.arg(i);
-        let val = match arg.to_nilable_str(true)? {
-            Some(v) => v,
-            None => continue,
+    #[close(hidden)]
+    fn close(&self, _: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        self.shutdown();
+        Ok(())
+    }
+}
+
+impl Process {
+    fn try_status(&self) -> Result<Option<ExitStatus>, Box<dyn std::error::Error>> {
+        // The process is running if it is being waited.
+        let mut prog = match self.prog.try_borrow_mut() {
+            Ok(v) => v,
+            Err(_) => return Ok(None),
         };
 
-        // Check if UTF-8.
-        let val = val
-            .as_utf8()
-            .ok_or_else(|| arg.error("expect UTF-8 string"))?;
+        prog.try_wait()
+            .map_err(|e| erdp::wrap("failed to get process status", e).into())
+    }
 
-        cmd.arg(val);
+    /// Send `sig` to the process or kill it if `sig` is [None].
+    fn signal(&self, sig: Option<i32>) -> Result<(), Box<dyn std::error::Error>> {
+        signal_child(&self.prog, self.pid, self.group, sig)
     }
 
-    if let Some(v) = opts.cwd {
-        cmd.current_dir(v);
+    /// Terminate the process with SIGTERM then kill it if it does not exit within the default
+    /// grace period.
+    ///
+    /// The process will be left as-is if it is being waited or terminated.
+    fn shutdown(&self) {
+        let mut prog = match self.prog.try_borrow_mut() {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        if !matches!(prog.try_wait(), Ok(None))
+            || send(Some(&mut *prog), self.pid, self.group, Some(TERM)).is_err()
+        {
+            return;
+        }
+
+        // We can't wait asynchronously once the script has finished so block until the process
+        // exited.
+        if self.reaper.is_closed() {
+            let deadline = std::time::Instant::now() + GRACE;
+
+            while std::time::Instant::now() < deadline {
+                if !matches!(prog.try_wait(), Ok(None)) {
+                    return;
+                }
+
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            send(Some(&mut *prog), self.pid, self.group, None).ok();
+            return;
+        }
+
+        drop(prog);
+
+        let prog = self.prog.clone();
+        let pid = self.pid;
+        let group = self.group;
+
+        self.reaper.spawn(async move {
+            reap(&mut prog.borrow_mut(), pid, group, GRACE).await.ok();
+        });
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.shutdown();
     }
+}
 
-    // Setup streams.
-    cmd.stdin(Stdio::null());
-    cmd.stderr(Stdio::inherit());
+/// Default signal for [Process::terminate()].
+#[cfg(unix)]
+pub const TERM: i32 = libc::SIGTERM;
+#[cfg(not(unix))]
+pub const TERM: i32 = 15;
+
+/// Send `sig` to `prog` or kill it if `sig` is [None].
+///
+/// This does nothing if `prog` already exited.
+fn signal_child(
+    prog: &RefCell<TrackedChild>,
+    pid: Option<u32>,
+    group: bool,
+    sig: Option<i32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Check if the process already exited. The process can't be reaped when it is being waited
+    // since the waiter will release the borrow as soon as the process is reaped.
+    let mut prog = prog.try_borrow_mut().ok();
+
+    if let Some(v) = &mut prog
+        && v.try_wait()?.is_some()
+    {
+        return Ok(());
+    }
 
-    match opts.stdout {
-        Stream::Null => cmd.stdout(Stdio::null()),
-        Stream::Inherit => cmd.stdout(Stdio::inherit()),
-        Stream::Pipe => cmd.stdout(Stdio::piped()),
-    };
+    send(prog.as_deref_mut(), pid, group, sig)
+}
 
-    // Setup environment vatiable.
-    match opts.env {
-        Env::Inherit => (),
-        Env::Clear => {
-            cmd.env_clear();
+/// Send `sig` to the process or kill it if `sig` is [None].
+///
+/// `prog` will be [None] if it is being waited. The caller must make sure the process has not been
+/// reaped. If `group` is `true` the signal will be sent to the whole process group instead.
+pub fn send(
+    prog: Option<&mut TrackedChild>,
+    pid: Option<u32>,
+    group: bool,
+    sig: Option<i32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // PID is None only when the process has been reaped so there is nothing to signal.
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        let pid: i32 = pid.try_into().unwrap();
+        let pid = if group { -pid } else { pid };
+        let sig = sig.unwrap_or(libc::SIGKILL);
+
+        if unsafe { libc::kill(pid, sig) } < 0 {
+            let e = std::io::Error::last_os_error();
+
+            return Err(erdp::wrap(format!("failed to send signal {sig} to {pid}"), e).into());
         }
-        Env::Update(t) => {
-            for i in t.deref() {
-                // Get name.
-                let (k, v) = i.unwrap();
-                let k = match &k {
-                    Value::Str(v) => v
-                        .as_utf8()
-                        .ok_or_else(|| arg.error("expect 'env' table with UTF-8 keys"))?,
-                    v => {
-                        return Err(arg.error(format!(
-                            "expect 'env' table with string keys, got {}",
-                            cx.type_name(v)
-                        )));
-                    }
-                };
+    }
 
-                // Get value.
-                let v = match &v {
-                    Value::False => {
-                        cmd.env_remove(k);
-                        continue;
-                    }
-                    Value::True => continue,
-                    Value::Str(v) => v
-                        .as_utf8()
-                        .ok_or_else(|| arg.error("expect 'env' table with UTF-8 values"))?,
-                    v => {
-                        return Err(arg.error(format!(
-                            "expect 'env' table with string or boolean values, got {}",
-                            cx.type_name(v)
-                        )));
-                    }
-                };
+    #[cfg(unix)]
+    let _ = prog;
 
-                cmd.env(k, v);
-            }
+    #[cfg(not(unix))]
+    let _ = (pid, group, sig);
+
+    #[cfg(not(unix))]
+    match prog {
+        Some(v) => v
+            .start_kill()
+            .map_err(|e| erdp::wrap("failed to kill the process", e))?,
+        None => return Err("the process is being waited by another thread".into()),
+    }
+
+    Ok(())
+}
+
+/// Wait for the process to exit and kill it if it still running after `grace`.
+pub async fn reap(
+    prog: &mut TrackedChild,
+    pid: Option<u32>,
+    group: bool,
+    grace: Duration,
+) -> Result<ExitStatus, std::io::Error> {
+    match tokio::time::timeout(grace, prog.wait()).await {
+        Ok(v) => v,
+        Err(_) => {
+            send(Some(prog), pid, group, None).ok();
+            prog.wait().await
         }
     }
+}
 
-    // Spawn.
-    let prog = cmd
-        .spawn()
-        .map_err(|e| erdp::wrap(format!("failed to spawn '{}'", opts.prog), e))?;
+/// Returns signal number for `name` (e.g. `TERM` or `SIGTERM`).
+fn parse_signal(name: &str) -> Option<i32> {
+    signal(name.strip_prefix("SIG").unwrap_or(name))
+}
 
-    Ok(prog)
+/// Returns signal number for `name` (e.g. `TERM`).
+#[cfg(unix)]
+fn signal(name: &str) -> Option<i32> {
+    let v = match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        _ => return None,
+    };
+
+    Some(v)
 }
 
-/// Class of the value that returned from `os.spawn`.
-pub struct Process(RefCell<Option<Child>>);
+/// Returns signal number for `name` (e.g. `TERM`).
+///
+/// Only `KILL` and `TERM` are supported on Windows, which both will terminate the process.
+#[cfg(not(unix))]
+fn signal(name: &str) -> Option<i32> {
+    match name {
+        "KILL" => Some(9),
+        "TERM" => Some(15),
+        _ => None,
+    }
+}
+
+/// Class of `stdin` property of the value returned from `os.spawn`.
+pub struct InputStream(RefCell<Option<ChildStdin>>);
 
 #[class(associated_data = App)]
-impl Process {
-    #[close(hidden)]
-    fn kill(&self, _: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
-        // Check if already killed.
-        let mut prog = match self.0.borrow_mut().take() {
-            Some(v) => v,
-            None => return Ok(()),
+impl InputStream {
+    async fn write(&self, cx: &Context<'_, App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        // Lock stream.
+        let mut st = self
+            .0
+            .try_borrow_mut()
+            .map_err(|_| "concurrent write is not supported")?;
+        let st = st.as_mut().ok_or("the stream already closed")?;
+
+        // Write.
+        let write = async {
+            for i in 2..=cx.args() {
+                let v = cx.arg(i).to_str()?;
+
+                st.write_all(v.as_bytes())
+                    .await
+                    .map_err(|e| erdp::wrap("failed to write stdin", e))?;
+            }
+
+            Ok::<_, Box<dyn std::error::Error>>(())
         };
 
-        // Kill.
-        let id = prog.id();
+        cx.associated_data().interrupt.guard(write).await??;
 
-        prog.kill()
-            .map_err(|e| erdp::wrap(format!("failed to kill {id}"), e))?;
-        prog.wait()
-            .map_err(|e| erdp::wrap(format!("failed to wait {id}"), e))?;
+        cx.push(Value::from_arg(&cx.arg(1)).unwrap())?;
 
         Ok(())
     }
-}
 
-impl Drop for Process {
-    fn drop(&mut self) {
-        let mut prog = match self.0.get_mut().take() {
-            Some(v) => v,
-            None => return,
-        };
+    fn close(&self, _: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        self.0
+            .try_borrow_mut()
+            .map_err(|_| "the stream is being written by another thread")?
+            .take();
 
-        prog.kill().unwrap();
-        prog.wait().unwrap();
+        Ok(())
     }
 }
 
-/// Class of `stdout` property of the value returned from `os.spawn`.
+/// Class of `stdout` and `stderr` property of the value returned from `os.spawn`.
 pub struct OutputStream(RefCell<OutputState>);
 
 #[class(associated_data = App)]
@@ -253,73 +558,456 @@ impl OutputStream {
             .0
             .try_borrow_mut()
             .map_err(|_| "concurrent read is not supported")?;
-        let st = st.deref_mut();
-        let rdr = match &mut st.rdr {
-            Some(v) => v,
-            None => return Ok(()),
-        };
 
-        // Read.
-        let buf = &mut st.buf;
+        cx.associated_data().interrupt.guard(st.read(cx)).await?
+    }
 
-        if cx.args() == 1 {
-            // Fill the buffer until LF or EOF.
-            let mut end = loop {
-                if let Some(i) = memchr(b'\n', buf) {
-                    break i;
-                }
+    fn lines(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        // The other stream will never be read while the iterator is blocking so the process will
+        // block forever once its pipe is full.
+        if self.0.borrow().both {
+            return Err("lines() cannot be used when both stdout and stderr are pipe".into());
+        }
 
-                if rdr.read_buf(buf).await? == 0 {
-                    st.rdr = None;
+        cx.push(fp!(Self::next_line))?;
+        cx.push(Value::from_arg(&cx.arg(1)).unwrap())?;
 
-                    if buf.is_empty() {
-                        return Ok(());
-                    }
+        Ok(())
+    }
+}
 
-                    break buf.len();
-                }
-            };
+impl OutputStream {
+    /// `busy` is a task that write the input or relay the outputs of the process. `both` is `true`
+    /// if both stdout and stderr are pipe.
+    fn new<'a, R>(
+        cx: &Context<'a, App, Args>,
+        rdr: R,
+        busy: Option<Rc<JoinHandle<()>>>,
+        deadline: Option<Rc<Deadline>>,
+        both: bool,
+    ) -> Ref<'a, UserData<App, Self>>
+    where
+        R: AsyncRead + RawStream + 'static,
+    {
+        cx.create_ud(Self(RefCell::new(OutputState {
+            #[cfg(unix)]
+            fd: rdr.as_raw_fd(),
+            rdr: Some(Box::pin(rdr)),
+            buf: Vec::new(),
+            busy,
+            #[cfg(unix)]
+            deadline,
+            both,
+        })))
+    }
 
-            cx.push_bytes(&buf[..end])?;
+    /// Iterator function returned from `lines`.
+    ///
+    /// Lua does not allow the iterator function of generic for to be an async function so we need
+    /// to block the current thread to wait for the data. The other tasks will not make any progress
+    /// while blocking, including the one that kill the process when timed out so
+    /// [OutputState::fill_blocking()] need to do it itself.
+    fn next_line(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+        let ud = cx.arg(1).get_ud::<Self>()?;
+        let mut st = ud
+            .value()
+            .0
+            .try_borrow_mut()
+            .map_err(|_| "concurrent read is not supported")?;
+
+        // Fill the buffer until LF or EOF.
+        let end = loop {
+            if let Some(i) = memchr(b'\n', &st.buf) {
+                break Some(i + 1);
+            }
 
-            // Remove pushed data.
-            if end < buf.len() {
-                end += 1;
+            // The process will never exit if it is waiting for the input or the pipe of the relayed
+            // output is full.
+            if st.busy.as_ref().is_some_and(|v| !v.is_finished()) {
+                return Err(
+                    "lines() cannot wait for the data while the input is being written or \
+                    the outputs are being relayed"
+                        .into(),
+                );
             }
 
-            buf.drain(..end);
-        } else {
-            return Err("non-default format currently not supported".into());
+            if !st.fill_blocking(&cx.associated_data().interrupt)? {
+                break (!st.buf.is_empty()).then_some(st.buf.len());
+            }
+        };
+
+        match end {
+            Some(v) => st.push_line(&cx, v, false)?,
+            None => cx.push(Nil)?,
         }
 
-        Ok(())
+        drop(st);
+
+        Ok(cx.into())
     }
 }
 
 struct OutputState {
+    #[cfg(unix)]
+    fd: std::os::fd::RawFd,
     rdr: Option<Pin<Box<dyn AsyncRead>>>,
     buf: Vec<u8>,
+    busy: Option<Rc<JoinHandle<()>>>,
+    #[cfg(unix)]
+    deadline: Option<Rc<Deadline>>,
+    both: bool,
 }
 
-/// First argument of `os.spawn`.
-struct Options<'a> {
-    prog: Cow<'a, str>,
-    cwd: Option<String>,
-    stdout: Stream,
-    env: Env<'a>,
+impl OutputState {
+    /// Implementation of `read` method of [OutputStream].
+    async fn read(
+        &mut self,
+        cx: &Context<'_, App, Args>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if cx.args() == 1 {
+            if !self.read_line(cx, false).await? {
+                cx.push(Nil)?;
+            }
+
+            return Ok(());
+        }
+
+        // Read with the specified formats. Stop on the first failure the same as file:read.
+        for i in 2..=cx.args() {
+            let arg = cx.arg(i);
+            let ok = if let Some(n) = arg.as_int(false) {
+                let n = usize::try_from(n).map_err(|_| arg.error("invalid count"))?;
+
+                self.read_count(cx, n).await?
+            } else if let Some(f) = arg.as_str(false) {
+                let f = f.as_bytes();
+                let f = f.strip_prefix(b"*").unwrap_or(f);
+
+                match f.first() {
+                    Some(b'l') => self.read_line(cx, false).await?,
+                    Some(b'L') => self.read_line(cx, true).await?,
+                    Some(b'a') => self.read_all(cx).await?,
+                    Some(b'n') => self.read_number(cx).await?,
+                    _ => return Err(arg.error("invalid format")),
+                }
+            } else {
+                return Err(arg.invalid_type("integer or string"));
+            };
+
+            if !ok {
+                cx.push(Nil)?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `false` if the buffer is empty and end of stream has been reached.
+    async fn read_line(
+        &mut self,
+        cx: &Context<'_, App, Args>,
+        keep: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // Fill the buffer until LF or EOF.
+        let end = loop {
+            if let Some(i) = memchr(b'\n', &self.buf) {
+                break i + 1;
+            }
+
+            if !self.fill().await? {
+                if self.buf.is_empty() {
+                    return Ok(false);
+                }
+
+                break self.buf.len();
+            }
+        };
+
+        self.push_line(cx, end, keep)?;
+
+        Ok(true)
+    }
+
+    /// This always returns `true`.
+    async fn read_all(
+        &mut self,
+        cx: &Context<'_, App, Args>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        while self.fill().await? {}
+
+        cx.push_bytes(std::mem::take(&mut self.buf))?;
+
+        Ok(true)
+    }
+
+    /// Returns `false` if end of stream has been reached before any byte is read.
+    async fn read_count(
+        &mut self,
+        cx: &Context<'_, App, Args>,
+        n: usize,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // Zero count is a test for end of stream.
+        while self.buf.len() < n.max(1) {
+            if !self.fill().await? {
+                break;
+            }
+        }
+
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+
+        let n = n.min(self.buf.len());
+
+        cx.push_bytes(&self.buf[..n])?;
+
+        self.buf.drain(..n);
+
+        Ok(true)
+    }
+
+    /// Returns `false` if the data is not a valid numeral.
+    ///
+    /// This follows the same rules as `read_number` in liolib.c.
+    async fn read_number(
+        &mut self,
+        cx: &Context<'_, App, Args>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // Skip whitespaces.
+        loop {
+            match self.buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(i) => {
+                    self.buf.drain(..i);
+                    break;
+                }
+                None => self.buf.clear(),
+            }
+
+            if !self.fill().await? {
+                return Ok(false);
+            }
+        }
+
+        // Get numeral.
+        let mut n = 0;
+        let mut count = 0;
+        let mut hex = false;
+
+        self.accept(&mut n, b"-+").await?;
+
+        if self.accept(&mut n, b"0").await? {
+            if self.accept(&mut n, b"xX").await? {
+                hex = true;
+            } else {
+                count = 1;
+            }
+        }
+
+        count += self.accept_digits(&mut n, hex).await?;
+
+        if self.accept(&mut n, b".").await? {
+            count += self.accept_digits(&mut n, hex).await?;
+        }
+
+        if count > 0 && self.accept(&mut n, if hex { b"pP" } else { b"eE" }).await? {
+            self.accept(&mut n, b"-+").await?;
+            self.accept_digits(&mut n, false).await?;
+        }
+
+        // Convert to number.
+        let v = cx
+            .create_str(std::str::from_utf8(&self.buf[..n]).unwrap())
+            .to_num();
+
+        self.buf.drain(..n);
+
+        match v {
+            Some(v) => cx.push(v)?,
+            None => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Returns `true` if the byte at `n` is one of `set`, in which case `n` will be incremented.
+    ///
+    /// Lua limit the length of numeral to 200 so this will stop at that point.
+    async fn accept(&mut self, n: &mut usize, set: &[u8]) -> Result<bool, std::io::Error> {
+        if *n >= 200 {
+            return Ok(false);
+        }
+
+        while self.buf.len() <= *n {
+            if !self.fill().await? {
+                return Ok(false);
+            }
+        }
+
+        if !set.contains(&self.buf[*n]) {
+            return Ok(false);
+        }
+
+        *n += 1;
+
+        Ok(true)
+    }
+
+    async fn accept_digits(&mut self, n: &mut usize, hex: bool) -> Result<usize, std::io::Error> {
+        let set: &[u8] = match hex {
+            true => b"0123456789abcdefABCDEF",
+            false => b"0123456789",
+        };
+        let mut count = 0;
+
+        while self.accept(n, set).await? {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn push_line(
+        &mut self,
+        cx: &Context<App, Args>,
+        end: usize,
+        keep: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let line = &self.buf[..end];
+        let line = match line.strip_suffix(b"\n") {
+            Some(v) if !keep => v,
+            _ => line,
+        };
+
+        cx.push_bytes(line)?;
+
+        self.buf.drain(..end);
+
+        Ok(())
+    }
+
+    /// Returns `false` if end of stream has been reached.
+    async fn fill(&mut self) -> Result<bool, std::io::Error> {
+        let rdr = match &mut self.rdr {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        if rdr.read_buf(&mut self.buf).await? == 0 {
+            self.rdr = None;
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Blocking version of [Self::fill()].
+    #[cfg(unix)]
+    fn fill_blocking(&mut self, interrupt: &Interrupt) -> Result<bool, Box<dyn std::error::Error>> {
+        use std::io::{Error, ErrorKind};
+
+        if self.rdr.is_none() {
+            return Ok(false);
+        }
+
+        // The file descriptor is non-blocking so we need to wait for it.
+        let mut buf = [0u8; 8192];
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let n = loop {
+            // We can't receive the interrupt from the runtime so we need to check it periodically.
+            // The same for the timeout since the task that kill the process can't run.
+            interrupt.check_blocking()?;
+
+            if let Some(v) = &self.deadline {
+                v.check();
+            }
+
+            match unsafe { libc::poll(&mut pfd, 1, 100) } {
+                0 => continue,
+                n if n < 0 => {
+                    let e = Error::last_os_error();
+
+                    match e.kind() {
+                        ErrorKind::Interrupted => continue,
+                        _ => return Err(e.into()),
+                    }
+                }
+                _ => {}
+            }
+
+            match unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) } {
+                n if n < 0 => {
+                    let e = Error::last_os_error();
+
+                    match e.kind() {
+                        ErrorKind::Interrupted | ErrorKind::WouldBlock => continue,
+                        // The pseudo-terminal master will return EIO when all slaves has been
+                        // closed.
+                        _ if e.raw_os_error() == Some(libc::EIO) => break 0,
+                        _ => return Err(e.into()),
+                    }
+                }
+                n => break n as usize,
+            }
+        };
+
+        if n == 0 {
+            self.rdr = None;
+            return Ok(false);
+        }
+
+        self.buf.extend_from_slice(&buf[..n]);
+
+        Ok(true)
+    }
+
+    #[cfg(not(unix))]
+    fn fill_blocking(&mut self, _: &Interrupt) -> Result<bool, Box<dyn std::error::Error>> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+    }
 }
 
-/// Option of standard stream for `os.spawn`.
-#[derive(FromStr)]
-enum Stream {
-    Null,
-    Inherit,
-    Pipe,
+/// Timeout of the process from `os.spawn`.
+struct Deadline {
+    at: Instant,
+    prog: Weak<RefCell<TrackedChild>>,
+    pid: Option<u32>,
+    group: bool,
 }
 
-/// Option for environment variable.
-enum Env<'a> {
-    Inherit,
-    Clear,
-    Update(Ref<'a, Table<App>>),
+impl Deadline {
+    /// Kill the process if the deadline has been reached and it still running.
+    ///
+    /// This does nothing if the process is being waited since the waiter will kill it.
+    fn check(&self) {
+        if Instant::now() < self.at {
+            return;
+        }
+
+        if let Some(v) = self.prog.upgrade()
+            && let Ok(mut v) = v.try_borrow_mut()
+            && let Ok(None) = v.try_wait()
+        {
+            send(Some(&mut v), self.pid, self.group, None).ok();
+        }
+    }
 }
+
+/// Underlying OS stream of [OutputStream].
+#[cfg(unix)]
+trait RawStream: std::os::fd::AsRawFd {}
+
+#[cfg(unix)]
+impl<T: std::os::fd::AsRawFd> RawStream for T {}
+
+/// Underlying OS stream of [OutputStream].
+#[cfg(not(unix))]
+trait RawStream {}
+
+#[cfg(not(unix))]
+impl<T> RawStream for T {}
@@ -0,0 +1,812 @@
+use super::prefix::Prefix;
+use super::spawn::send;
+use super::usage::TrackedChild;
+use super::{GRACE, ProcessFailed, push_status};
+use crate::App;
+use crate::api::{Interrupt, get_duration};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::pin::pin;
+use std::process::{ExitStatus, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tsuki::context::{Arg, Args, Context};
+use tsuki::{FromStr, Table, Value};
+
+/// Process specification accepted by `os.run`, `os.capture` and `os.spawn`.
+///
+/// The first argument can be either a program name or a table with the program name at index #1
+/// followed by its arguments and the options. The remaining arguments of the function will be
+/// appended to the program arguments with `nil` removed.
+pub struct Spec {
+    pub prog: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    pub env: Env,
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+    pub timeout: Option<Duration>,
+    pub group: Option<bool>,
+    pub check: bool,
+    pub prefix: Option<Prefix>,
+    interrupt: Rc<Interrupt>,
+}
+
+impl Spec {
+    pub fn from_args(cx: &Context<App, Args>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut spec = Self::from_arg(cx, &cx.arg(1))?;
+
+        // Get arguments.
+        for i in 2..=cx.args() {
+            // Get argument.
+            let arg = cx.arg(i);
+            let val = match arg.to_nilable_str(true)? {
+                Some(v) => v,
+                None => continue,
+            };
+
+            // Check if UTF-8.
+            let val = val
+                .as_utf8()
+                .ok_or_else(|| arg.error("expect UTF-8 string"))?;
+
+            spec.args.push(val.to_owned());
+        }
+
+        Ok(spec)
+    }
+
+    /// Parse the specification from a single argument without the remaining arguments.
+    pub fn from_arg(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let spec = if let Some(prog) = arg.as_str(true) {
+            let prog = prog
+                .as_utf8()
+                .ok_or_else(|| arg.error("expect UTF-8 string"))?;
+
+            Self::new(cx, prog.to_owned())
+        } else if let Some(t) = arg.as_table() {
+            Self::from_table(cx, arg, t)?
+        } else {
+            return Err(arg.invalid_type("string or table"));
+        };
+
+        Ok(spec)
+    }
+
+    /// Create a specification for `prog` with default options.
+    pub fn new(cx: &Context<App, Args>, prog: String) -> Self {
+        Self {
+            prog,
+            args: Vec::new(),
+            cwd: None,
+            env: Env::Inherit,
+            stdin: Input::Null,
+            stdout: Output::Inherit,
+            stderr: Output::Inherit,
+            timeout: None,
+            group: None,
+            check: true,
+            prefix: None,
+            interrupt: cx.associated_data().interrupt.clone(),
+        }
+    }
+
+    pub fn from_table(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        t: &Table<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // Get program name and its arguments.
+        let mut prog = None;
+        let mut args = Vec::new();
+
+        for i in 1..=t.len() {
+            let v = match t.get(i) {
+                Value::Str(v) => v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error(format!("expect UTF-8 string at index #{i}")))?
+                    .to_owned(),
+                v => {
+                    return Err(arg.error(format!(
+                        "expect string at index #{i}, got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            match prog {
+                Some(_) => args.push(v),
+                None => prog = Some(v),
+            }
+        }
+
+        let prog = prog.ok_or_else(|| arg.error("expect string at index #1, got nil"))?;
+
+        // Get cwd.
+        let cwd = match t.get_str_key("cwd") {
+            Value::Nil => None,
+            Value::Str(v) => v
+                .as_utf8()
+                .ok_or_else(|| arg.error("expect UTF-8 string on 'cwd'"))?
+                .to_owned()
+                .into(),
+            v => return Err(arg.error(format!("expect string on 'cwd', got {}", cx.type_name(v)))),
+        };
+
+        // Get env.
+        let env = Env::from_value(cx, arg, t.get_str_key("env"))?;
+
+        // Get stdin.
+        let stdin = match (t.get_str_key("stdin"), t.get_str_key("input")) {
+            (Value::Nil, Value::Nil) => Input::Null,
+            (Value::Nil, Value::Str(v)) => Input::Data(v.as_bytes().to_vec()),
+            (Value::Nil, v) => {
+                return Err(arg.error(format!("expect string on 'input', got {}", cx.type_name(v))));
+            }
+            (v, Value::Nil) => Input::from_value(cx, arg, v)?,
+            _ => return Err(arg.error("'stdin' and 'input' cannot be used together")),
+        };
+
+        // Get stdout and stderr.
+        let stdout = Output::from_value(cx, arg, "stdout", t.get_str_key("stdout"))?;
+        let stderr = Output::from_value(cx, arg, "stderr", t.get_str_key("stderr"))?;
+
+        // Get timeout.
+        let timeout = get_duration(cx, arg, "timeout", t.get_str_key("timeout"))?;
+
+        // Get group.
+        let group = match t.get_str_key("group") {
+            Value::Nil => None,
+            Value::True => Some(true),
+            Value::False => Some(false),
+            v => {
+                return Err(arg.error(format!(
+                    "expect boolean on 'group', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        // Get check.
+        let check = match t.get_str_key("check") {
+            Value::Nil | Value::True => true,
+            Value::False => false,
+            v => {
+                return Err(arg.error(format!(
+                    "expect boolean on 'check', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        // Get prefix.
+        let prefix = match t.get_str_key("prefix") {
+            Value::Nil => None,
+            Value::Str(v) => v
+                .as_utf8()
+                .map(|v| Prefix::new(v.to_owned()))
+                .ok_or_else(|| arg.error("expect UTF-8 string on 'prefix'"))?
+                .into(),
+            v => {
+                return Err(arg.error(format!(
+                    "expect string on 'prefix', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        Ok(Self {
+            prog,
+            args,
+            cwd,
+            env,
+            stdin,
+            stdout,
+            stderr,
+            timeout,
+            group,
+            check,
+            prefix,
+            interrupt: cx.associated_data().interrupt.clone(),
+        })
+    }
+
+    /// Spawn the process.
+    ///
+    /// The process will be killed when the returned [Child] is dropped.
+    pub fn spawn(&self) -> Result<Child, Box<dyn std::error::Error>> {
+        let mut cmd = self.command()?;
+
+        #[cfg(unix)]
+        if self.group() {
+            cmd.process_group(0);
+        }
+
+        self.start(&mut cmd)
+    }
+
+    /// Returns `true` if the process should be a leader of a new process group.
+    ///
+    /// This is enabled by default when `timeout` is specified so all of its descendants will be
+    /// killed when timed out. It is not enabled otherwise since the process on a background process
+    /// group can't read from the terminal, which mean only the process itself will be killed when
+    /// the future of [Self::wait()] was dropped. `os.spawn`, `os.group` and `os.supervise` default
+    /// to `true` instead.
+    pub fn group(&self) -> bool {
+        self.group.unwrap_or(self.timeout.is_some())
+    }
+
+    /// Build [Command] for this specification.
+    pub fn command(&self) -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::new(&self.prog);
+
+        cmd.args(&self.args);
+
+        if let Some(v) = &self.cwd {
+            cmd.current_dir(v);
+        }
+
+        self.env.apply(&mut cmd);
+
+        // Setup streams.
+        match &self.stdin {
+            Input::Null => cmd.stdin(Stdio::null()),
+            Input::Inherit => cmd.stdin(Stdio::inherit()),
+            Input::File(p) => {
+                let p = self.resolve(p);
+                let f = File::open(&p)
+                    .map_err(|e| erdp::wrap(format!("failed to open {}", p.display()), e))?;
+
+                cmd.stdin(f)
+            }
+            Input::Data(_) | Input::Pipe => cmd.stdin(Stdio::piped()),
+        };
+
+        let stdout = match &self.stdout {
+            Output::Null => {
+                cmd.stdout(Stdio::null());
+                None
+            }
+            Output::Inherit if self.prefix.is_none() => {
+                cmd.stdout(Stdio::inherit());
+                None
+            }
+            Output::Inherit | Output::Pipe => {
+                cmd.stdout(Stdio::piped());
+                None
+            }
+            Output::File(p, a) => {
+                let f = Output::open(&self.resolve(p), *a)?;
+                let c = f
+                    .try_clone()
+                    .map_err(|e| erdp::wrap(format!("failed to duplicate {p}"), e))?;
+
+                cmd.stdout(f);
+                Some(c)
+            }
+            Output::Stdout => unreachable!(),
+        };
+
+        match &self.stderr {
+            Output::Null => cmd.stderr(Stdio::null()),
+            Output::Inherit if self.prefix.is_none() => cmd.stderr(Stdio::inherit()),
+            Output::Inherit | Output::Pipe => cmd.stderr(Stdio::piped()),
+            Output::File(p, a) => cmd.stderr(Output::open(&self.resolve(p), *a)?),
+            Output::Stdout => match (&self.stdout, stdout) {
+                (Output::Null, _) => cmd.stderr(Stdio::null()),
+                (Output::Inherit, _) if self.prefix.is_none() => cmd.stderr(std::io::stdout()),
+                (Output::Inherit, _) => cmd.stderr(Stdio::piped()),
+                (_, Some(f)) => cmd.stderr(f),
+                _ => return Err("merging stderr into piped stdout is not supported".into()),
+            },
+        };
+        cmd.kill_on_drop(true);
+
+        Ok(cmd)
+    }
+
+    /// Resolve `path` of the redirection relative to `cwd` when it is set, which is the same as
+    /// how the process itself see it.
+    fn resolve<'a>(&self, path: &'a str) -> Cow<'a, Path> {
+        match &self.cwd {
+            Some(v) => Cow::Owned(Path::new(v).join(path)),
+            None => Cow::Borrowed(Path::new(path)),
+        }
+    }
+
+    /// Spawn `cmd`, which was built from [Self::command()].
+    pub fn start(&self, cmd: &mut Command) -> Result<Child, Box<dyn std::error::Error>> {
+        self.interrupt.check()?;
+
+        cmd.spawn()
+            .map_err(|e| erdp::wrap(format!("failed to spawn '{}'", self.prog), e).into())
+    }
+
+    /// Same as [Self::start()] but returns [TrackedChild] to collect its resource usage.
+    pub fn start_tracked(
+        &self,
+        cmd: &mut Command,
+    ) -> Result<TrackedChild, Box<dyn std::error::Error>> {
+        self.interrupt.check()?;
+
+        TrackedChild::spawn(cmd)
+            .map_err(|e| erdp::wrap(format!("failed to spawn '{}'", self.prog), e).into())
+    }
+
+    /// Returns `true` if the future from [Self::feed()] will write any data.
+    pub fn feeding(&self) -> bool {
+        matches!(self.stdin, Input::Data(_))
+    }
+
+    /// Returns a future to write `data` on `stdin` to the process.
+    ///
+    /// `stdin` will be taken if the future need it.
+    pub fn feed(&self, stdin: &mut Option<ChildStdin>) -> impl Future<Output = ()> + 'static {
+        let (stdin, data) = match &self.stdin {
+            Input::Data(v) => (stdin.take(), Some(v.clone())),
+            _ => (None, None),
+        };
+
+        async move {
+            // The process may exit without reading all of the input so ignore the error.
+            if let (Some(mut w), Some(v)) = (stdin, data) {
+                w.write_all(&v).await.ok();
+            }
+        }
+    }
+
+    /// Returns `true` if the future from [Self::relay()] will relay any output.
+    pub fn relaying(&self) -> bool {
+        self.prefix.is_some()
+            && (matches!(self.stdout, Output::Inherit) || matches!(self.stderr, Output::Inherit))
+    }
+
+    /// Returns a future to write the inherited outputs of the process to Project with `prefix`.
+    ///
+    /// `stdout` and `stderr` will be taken if the future need it.
+    pub fn relay(
+        &self,
+        stdout: &mut Option<ChildStdout>,
+        stderr: &mut Option<ChildStderr>,
+    ) -> impl Future<Output = ()> + 'static {
+        let prefix = self.prefix.clone();
+        let (stdout, stderr) = match &prefix {
+            Some(_) => (
+                match &self.stdout {
+                    Output::Inherit => stdout.take(),
+                    _ => None,
+                },
+                match (&self.stderr, &self.stdout) {
+                    (Output::Inherit, _) | (Output::Stdout, Output::Inherit) => stderr.take(),
+                    _ => None,
+                },
+            ),
+            None => (None, None),
+        };
+        let merged = matches!(self.stderr, Output::Stdout);
+
+        async move {
+            if let Some(p) = prefix {
+                tokio::join!(p.relay(stdout, false), p.relay(stderr, !merged));
+            }
+        }
+    }
+
+    /// Wait for `f` within `timeout`.
+    ///
+    /// If Project was interrupted while waiting the signal will be forwarded to `pid` then `f` will
+    /// be given a grace period to complete before [Interrupted](crate::api::Interrupted) is
+    /// returned. The same for `exit` from a detached task except `pid` will receive SIGTERM and
+    /// [Exit](crate::api::Exit) will be returned instead. If `group` is `true` the signal will be
+    /// sent to the whole process group instead. The returned future does not borrow `self`.
+    pub fn wait<F: Future>(
+        &self,
+        pid: Option<u32>,
+        group: bool,
+        f: F,
+    ) -> impl Future<Output = Result<F::Output, Box<dyn std::error::Error>>> + use<F> {
+        let prog = self.prog.clone();
+        let timeout = self.timeout;
+        let interrupt = self.interrupt.clone();
+
+        async move {
+            let f = async {
+                match timeout {
+                    Some(v) => tokio::time::timeout(v, f).await.map_err(|_| {
+                        // The process itself will be killed when f is dropped but not its
+                        // descendants.
+                        send(None, pid, group, None).ok();
+
+                        format!("'{}' did not exit within {} seconds", prog, v.as_secs_f64())
+                    }),
+                    None => Ok(f.await),
+                }
+            };
+
+            let mut f = pin!(f);
+            let e = tokio::select! {
+                v = &mut f => return Ok(v?),
+                e = interrupt.cancelled() => e,
+            };
+
+            // SIGINT from the terminal is already delivered to the process if it is on the same
+            // process group as us.
+            #[cfg(unix)]
+            {
+                use super::spawn::TERM;
+                use crate::api::Interrupted;
+
+                let sig = e.downcast_ref::<Interrupted>().map_or(TERM, |v| v.signal());
+
+                if group || sig != libc::SIGINT {
+                    send(None, pid, group, Some(sig)).ok();
+                }
+            }
+
+            #[cfg(not(unix))]
+            let _ = (pid, group);
+
+            tokio::time::timeout(GRACE, f).await.ok();
+
+            Err(e)
+        }
+    }
+
+    /// Raise [ProcessFailed] if `check` is enabled and the process was failed.
+    pub fn check(&self, status: ExitStatus, stderr: Option<&[u8]>) -> Result<(), ProcessFailed> {
+        if self.check && !status.success() {
+            Err(ProcessFailed::new(
+                self.prog.clone(),
+                self.args.clone(),
+                status,
+                stderr.map(|v| v.to_vec()),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Push exit code and the signal that terminated the process (if any) to `cx` if `check` is
+    /// disabled.
+    pub fn push_status<T>(
+        &self,
+        cx: &Context<App, T>,
+        status: ExitStatus,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.check {
+            return Ok(());
+        }
+
+        push_status(cx, status)
+    }
+
+    /// Parse a table of name to specification, which is used by `f`.
+    ///
+    /// The result is sorted by name with a distinct prefix assigned to each specification that does
+    /// not have one. `map` will receive the table of the specification if it was specified as a
+    /// table.
+    pub fn from_named<T>(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        f: &str,
+        mut map: impl FnMut(String, Self, Option<&Table<App>>) -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        // Get specifications.
+        let t = arg.get_table()?;
+        let mut specs = Vec::new();
+
+        for i in t {
+            let (k, v) = i.unwrap();
+            let name = match &k {
+                Value::Str(v) => v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error("expect table with UTF-8 keys"))?
+                    .to_owned(),
+                v => {
+                    return Err(arg.error(format!(
+                        "expect table with string keys, got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            let (spec, t) = match v {
+                Value::Str(v) => v
+                    .as_utf8()
+                    .map(|v| (Self::new(cx, v.to_owned()), None))
+                    .ok_or_else(|| arg.error(format!("expect UTF-8 string on '{name}'")))?,
+                Value::Table(v) => (Self::from_table(cx, arg, &v)?, Some(v)),
+                v => {
+                    return Err(arg.error(format!(
+                        "expect string or table on '{name}', got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            spec.deny_pipe(arg, f)?;
+
+            specs.push((name, spec, t));
+        }
+
+        if specs.is_empty() {
+            return Err(arg.error("expect at least one process"));
+        }
+
+        // Set prefixes. The table order is not deterministic so we need to sort it.
+        let width = specs.iter().map(|s| s.0.chars().count()).max().unwrap();
+
+        specs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        specs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, mut spec, t))| {
+                if spec.prefix.is_none() {
+                    spec.prefix = Some(Prefix::with_color(format!("{name:width$}"), i));
+                }
+
+                map(name, spec, t.as_deref())
+            })
+            .collect()
+    }
+
+    /// Raise an error if any of standard stream is `pipe`, which is not supported by `f`.
+    pub fn deny_pipe(&self, arg: &Arg<App>, f: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if matches!(self.stdin, Input::Pipe) {
+            return Err(arg.error(format!("'pipe' on 'stdin' is not supported by '{f}'")));
+        }
+
+        for (n, v) in [("stdout", &self.stdout), ("stderr", &self.stderr)] {
+            if matches!(v, Output::Pipe) {
+                return Err(arg.error(format!("'pipe' on '{n}' is not supported by '{f}'")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Option for environment variable.
+pub enum Env {
+    Inherit,
+    Clear,
+    Update(Vec<(String, Option<String>)>),
+}
+
+impl Env {
+    fn from_value(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        v: Value<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let t = match v {
+            Value::Nil | Value::True => return Ok(Self::Inherit),
+            Value::False => return Ok(Self::Clear),
+            Value::Table(t) => t,
+            v => {
+                return Err(arg.error(format!(
+                    "expect boolean or table on 'env', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        // Get variables.
+        let mut vars = Vec::new();
+
+        for i in &*t {
+            // Get name.
+            let (k, v) = i.unwrap();
+            let k = match &k {
+                Value::Str(v) => v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error("expect 'env' table with UTF-8 keys"))?,
+                v => {
+                    return Err(arg.error(format!(
+                        "expect 'env' table with string keys, got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            // Get value.
+            let v = match &v {
+                Value::False => None,
+                Value::True => continue,
+                Value::Str(v) => v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error("expect 'env' table with UTF-8 values"))?
+                    .to_owned()
+                    .into(),
+                v => {
+                    return Err(arg.error(format!(
+                        "expect 'env' table with string or boolean values, got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            vars.push((k.to_owned(), v));
+        }
+
+        Ok(Self::Update(vars))
+    }
+
+    fn apply(&self, cmd: &mut Command) {
+        match self {
+            Self::Inherit => (),
+            Self::Clear => {
+                cmd.env_clear();
+            }
+            Self::Update(vars) => {
+                for (k, v) in vars {
+                    match v {
+                        Some(v) => cmd.env(k, v),
+                        None => cmd.env_remove(k),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Option for stdin.
+pub enum Input {
+    Null,
+    Inherit,
+    File(String),
+    Data(Vec<u8>),
+    Pipe,
+}
+
+impl Input {
+    fn from_value(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        v: Value<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let v = match v {
+            Value::Str(v) => {
+                let v = v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error("expect UTF-8 string on 'stdin'"))?;
+
+                match v.parse() {
+                    Ok(InputMode::Null) => Self::Null,
+                    Ok(InputMode::Inherit) => Self::Inherit,
+                    Ok(InputMode::Pipe) => Self::Pipe,
+                    Err(_) => return Err(arg.error(format!("unknown option '{v}' on 'stdin'"))),
+                }
+            }
+            Value::Table(v) => match (v.get_str_key("file"), v.get_str_key("data")) {
+                (Value::Str(v), Value::Nil) => v
+                    .as_utf8()
+                    .map(|v| Self::File(v.to_owned()))
+                    .ok_or_else(|| arg.error("expect UTF-8 string on 'stdin.file'"))?,
+                (Value::Nil, Value::Str(v)) => Self::Data(v.as_bytes().to_vec()),
+                _ => {
+                    return Err(arg.error(
+                        "expect table with either 'file' or 'data' as a string on 'stdin'",
+                    ));
+                }
+            },
+            v => {
+                return Err(arg.error(format!(
+                    "expect string or table on 'stdin', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        Ok(v)
+    }
+}
+
+/// Option of `stdin` when it is a string.
+#[derive(FromStr)]
+enum InputMode {
+    Null,
+    Inherit,
+    Pipe,
+}
+
+/// Option for stdout and stderr.
+pub enum Output {
+    Null,
+    Inherit,
+    Pipe,
+    File(String, bool),
+    Stdout,
+}
+
+impl Output {
+    fn from_value(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        name: &str,
+        v: Value<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let v = match v {
+            Value::Nil => Self::Inherit,
+            Value::Str(v) => {
+                let v = v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error(format!("expect UTF-8 string on '{name}'")))?;
+
+                match v.parse() {
+                    Ok(OutputMode::Null) => Self::Null,
+                    Ok(OutputMode::Inherit) => Self::Inherit,
+                    Ok(OutputMode::Pipe) => Self::Pipe,
+                    Ok(OutputMode::Stdout) if name == "stderr" => Self::Stdout,
+                    _ => return Err(arg.error(format!("unknown option '{v}' on '{name}'"))),
+                }
+            }
+            Value::Table(t) => {
+                let path = match t.get_str_key("file") {
+                    Value::Str(v) => v
+                        .as_utf8()
+                        .ok_or_else(|| arg.error(format!("expect UTF-8 string on '{name}.file'")))?
+                        .to_owned(),
+                    v => {
+                        return Err(arg.error(format!(
+                            "expect string on '{name}.file', got {}",
+                            cx.type_name(v)
+                        )));
+                    }
+                };
+
+                let append = match t.get_str_key("append") {
+                    Value::Nil | Value::False => false,
+                    Value::True => true,
+                    v => {
+                        return Err(arg.error(format!(
+                            "expect boolean on '{name}.append', got {}",
+                            cx.type_name(v)
+                        )));
+                    }
+                };
+
+                Self::File(path, append)
+            }
+            v => {
+                return Err(arg.error(format!(
+                    "expect string or table on '{name}', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        Ok(v)
+    }
+
+    fn open(path: &Path, append: bool) -> Result<File, Box<dyn std::error::Error>> {
+        let mut opts = OpenOptions::new();
+
+        if append {
+            opts.append(true);
+        } else {
+            opts.write(true).truncate(true);
+        }
+
+        opts.create(true)
+            .open(path)
+            .map_err(|e| erdp::wrap(format!("failed to open {}", path.display()), e).into())
+    }
+}
+
+/// Option of stdout and stderr when it is a string.
+#[derive(FromStr)]
+enum OutputMode {
+    Null,
+    Inherit,
+    Pipe,
+    Stdout,
+}
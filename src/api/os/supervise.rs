@@ -0,0 +1,695 @@
+use super::reaper::Reaper;
+use super::spawn::{TERM, reap, send};
+use super::spec::{Output, Spec};
+use super::usage::TrackedChild;
+use super::{GRACE, push_status};
+use crate::App;
+use crate::api::{Interrupt, Interrupted, get_duration};
+use erdp::ErrorDisplay;
+use std::cell::{Cell, RefCell};
+use std::process::ExitStatus;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tsuki::context::{Arg, Args, Context, Ret};
+use tsuki::{Table, Value, class};
+
+/// Implementation of `os.supervise`.
+pub fn entry(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get services.
+    let arg = cx.arg(1);
+    let services = Spec::from_named(&cx, &arg, "supervise", |name, spec, t| {
+        // The service is always on its own process group.
+        if spec.group == Some(false) {
+            return Err(arg.error(format!("'group' cannot be disabled on '{name}'")));
+        }
+
+        let mut svc = Service::new(name, spec);
+
+        if let Some(t) = t {
+            svc.parse_options(&cx, &arg, t)?;
+        }
+
+        Ok(svc)
+    })?;
+
+    // Start services.
+    let app = cx.associated_data();
+    let state = Rc::new(State {
+        phases: RefCell::new(vec![Phase::Starting; services.len()]),
+        changed: Notify::new(),
+        stop: Cell::new(false),
+        stopping: Notify::new(),
+    });
+    let names = services.iter().map(|s| s.name.clone()).collect();
+    let tasks: Vec<JoinHandle<()>> = services
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let state = state.clone();
+            let interrupt = app.interrupt.clone();
+
+            tokio::task::spawn_local(s.run(i, state, interrupt))
+        })
+        .collect();
+
+    let services = Rc::new(Services {
+        state,
+        tasks: RefCell::new(tasks),
+    });
+
+    // Make sure the services are stopped gracefully if the supervisor still alive when the script
+    // finished.
+    let weak = Rc::downgrade(&services);
+
+    app.reaper.defer(move |r| {
+        if let Some(v) = weak.upgrade() {
+            v.shutdown(r);
+        }
+    });
+
+    cx.push(cx.create_ud(Supervisor {
+        names,
+        services,
+        reaper: app.reaper.clone(),
+    }))?;
+
+    Ok(cx.into())
+}
+
+/// Class of the value that returned from `os.supervise`.
+pub struct Supervisor {
+    names: Vec<String>,
+    services: Rc<Services>,
+    reaper: Rc<Reaper>,
+}
+
+#[class(associated_data = App)]
+impl Supervisor {
+    async fn wait(&self, cx: &Context<'_, App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let interrupt = &cx.associated_data().interrupt;
+
+        loop {
+            // Check if any service gave up.
+            let changed = self.services.state.changed.notified();
+            let phases = self.services.state.phases.borrow();
+            let stopped = phases.iter().enumerate().find_map(|(i, p)| match p {
+                Phase::Exited(v) | Phase::Failed(v) | Phase::Stopped(v) => Some((i, *v)),
+                _ => None,
+            });
+
+            drop(phases);
+
+            if let Some((i, status)) = stopped {
+                cx.push_str(self.names[i].as_str())?;
+
+                if let Some(v) = status {
+                    push_status(cx, v)?;
+                }
+
+                break;
+            }
+
+            interrupt.guard(changed).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn ready(&self, cx: &Context<'_, App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let interrupt = &cx.associated_data().interrupt;
+
+        loop {
+            // Check if all services are ready.
+            let changed = self.services.state.changed.notified();
+            let mut ready = true;
+
+            for (i, p) in self.services.state.phases.borrow().iter().enumerate() {
+                match p {
+                    Phase::Running | Phase::Healthy => {}
+                    Phase::Starting | Phase::Backoff => ready = false,
+                    Phase::Exited(_) | Phase::Failed(_) | Phase::Stopped(_) => {
+                        let n = &self.names[i];
+
+                        return Err(format!("service '{n}' stopped before it is ready").into());
+                    }
+                }
+            }
+
+            if ready {
+                break;
+            }
+
+            interrupt.guard(changed).await?;
+        }
+
+        Ok(())
+    }
+
+    fn status(&self, cx: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let t = cx.create_table();
+
+        for (n, p) in self
+            .names
+            .iter()
+            .zip(self.services.state.phases.borrow().iter())
+        {
+            let v = match p {
+                Phase::Starting => "starting",
+                Phase::Running => "running",
+                Phase::Healthy => "healthy",
+                Phase::Backoff => "backoff",
+                Phase::Exited(_) => "exited",
+                Phase::Failed(_) => "failed",
+                Phase::Stopped(_) => "stopped",
+            };
+
+            t.set_str_key(n.as_str(), cx.create_str(v));
+        }
+
+        cx.push(t)?;
+
+        Ok(())
+    }
+
+    async fn stop(&self, _: &Context<'_, App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        let tasks = std::mem::take(&mut *self.services.tasks.borrow_mut());
+
+        self.services.state.stop();
+
+        for t in tasks {
+            t.await.ok();
+        }
+
+        Ok(())
+    }
+
+    #[close(hidden)]
+    fn close(&self, _: &Context<App, Args>) -> Result<(), Box<dyn std::error::Error>> {
+        self.services.shutdown(&self.reaper);
+        Ok(())
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.services.shutdown(&self.reaper);
+    }
+}
+
+/// Services of a [Supervisor].
+struct Services {
+    state: Rc<State>,
+    tasks: RefCell<Vec<JoinHandle<()>>>,
+}
+
+impl Services {
+    /// Stop all services without waiting for them.
+    fn shutdown(&self, reaper: &Reaper) {
+        let tasks = match self.tasks.try_borrow_mut() {
+            Ok(mut v) => std::mem::take(&mut *v),
+            Err(_) => return,
+        };
+
+        if tasks.is_empty() {
+            return;
+        }
+
+        self.state.stop();
+
+        // The services will be killed when the tasks are dropped if the script already finished.
+        if !reaper.is_closed() {
+            reaper.spawn(async move {
+                for t in tasks {
+                    t.await.ok();
+                }
+            });
+        }
+    }
+}
+
+/// Shared state between [Supervisor] and its services.
+struct State {
+    phases: RefCell<Vec<Phase>>,
+    changed: Notify,
+    stop: Cell<bool>,
+    stopping: Notify,
+}
+
+impl State {
+    fn set(&self, i: usize, v: Phase) {
+        self.phases.borrow_mut()[i] = v;
+        self.changed.notify_waiters();
+    }
+
+    fn stop(&self) {
+        self.stop.set(true);
+        self.stopping.notify_waiters();
+    }
+
+    /// Wait until [Self::stop()] is called.
+    async fn stopped(&self) {
+        let stopping = self.stopping.notified();
+
+        if self.stop.get() {
+            return;
+        }
+
+        stopping.await;
+    }
+}
+
+/// Current phase of a service.
+#[derive(Clone, Copy)]
+enum Phase {
+    Starting,
+    Running,
+    Healthy,
+    Backoff,
+    Exited(Option<ExitStatus>),
+    Failed(Option<ExitStatus>),
+    Stopped(Option<ExitStatus>),
+}
+
+/// A service to supervise.
+struct Service {
+    name: String,
+    spec: Spec,
+    restart: Restart,
+    retries: u32,
+    backoff: Duration,
+    health: Option<Health>,
+}
+
+impl Service {
+    fn new(name: String, spec: Spec) -> Self {
+        Self {
+            name,
+            spec,
+            restart: Restart::OnFailure,
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            health: None,
+        }
+    }
+
+    /// Parse service options from `t`.
+    fn parse_options(
+        &mut self,
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        t: &Table<App>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Get restart policy.
+        let name = &self.name;
+
+        match t.get_str_key("restart") {
+            Value::Nil => {}
+            Value::Str(v) => {
+                self.restart = v
+                    .as_utf8()
+                    .ok_or_else(|| arg.error(format!("expect UTF-8 string on '{name}.restart'")))?
+                    .parse()
+                    .map_err(|e| arg.error(e))?
+            }
+            v => {
+                return Err(arg.error(format!(
+                    "expect string on '{name}.restart', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        }
+
+        // Get retries.
+        match t.get_str_key("retries") {
+            Value::Nil => {}
+            Value::Int(v) => {
+                self.retries = u32::try_from(v)
+                    .map_err(|_| arg.error(format!("value out of range on '{name}.retries'")))?
+            }
+            v => {
+                return Err(arg.error(format!(
+                    "expect integer on '{name}.retries', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        }
+
+        // Get backoff.
+        if let Some(v) = get_duration(
+            cx,
+            arg,
+            &format!("{name}.backoff"),
+            t.get_str_key("backoff"),
+        )? {
+            self.backoff = v;
+        }
+
+        // Get health check.
+        self.health = match t.get_str_key("health") {
+            Value::Nil => None,
+            Value::Table(v) => Some(Health::from_table(cx, arg, name, &v)?),
+            v => {
+                return Err(arg.error(format!(
+                    "expect table on '{name}.health', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        Ok(())
+    }
+
+    async fn run(self, i: usize, state: Rc<State>, interrupt: Rc<Interrupt>) {
+        let mut retries = 0;
+        let mut backoff = self.backoff;
+
+        loop {
+            // Start the service.
+            let started = Instant::now();
+            let (status, end) = match self.start() {
+                Ok(v) => {
+                    state.set(i, Phase::Starting);
+                    self.supervise(v, i, &state, &interrupt).await
+                }
+                Err(e) if e.is::<Interrupted>() => {
+                    state.set(i, Phase::Stopped(None));
+                    break;
+                }
+                Err(e) => {
+                    // Restarting will not help if the program cannot be started.
+                    eprintln!("Failed to start '{}': {}.", self.name, e.display());
+                    state.set(i, Phase::Failed(None));
+                    break;
+                }
+            };
+
+            // Check if we need to restart. The exit status of the service that was terminated by
+            // the health check does not tell whether it was failed since it may exit with zero code
+            // on SIGTERM.
+            let success = match end {
+                End::Exited => status.is_some_and(|v| v.success()),
+                End::Unhealthy => false,
+                End::Stopped => {
+                    state.set(i, Phase::Stopped(status));
+                    break;
+                }
+            };
+
+            match self.restart {
+                Restart::No => {
+                    state.set(i, Phase::Exited(status));
+                    break;
+                }
+                Restart::OnFailure if success => {
+                    state.set(i, Phase::Exited(status));
+                    break;
+                }
+                Restart::OnFailure | Restart::Always => {}
+            }
+
+            // Reset the backoff if the service was running long enough.
+            if started.elapsed() >= RESET {
+                retries = 0;
+                backoff = self.backoff;
+            }
+
+            if retries == self.retries {
+                state.set(i, Phase::Failed(status));
+                break;
+            }
+
+            retries += 1;
+
+            // Wait before restart.
+            state.set(i, Phase::Backoff);
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = state.stopped() => {
+                    state.set(i, Phase::Stopped(status));
+                    break;
+                }
+                _ = interrupt.wait() => {
+                    state.set(i, Phase::Stopped(status));
+                    break;
+                }
+            }
+
+            backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+        }
+    }
+
+    fn start(&self) -> Result<Running, Box<dyn std::error::Error>> {
+        let mut cmd = self.spec.command()?;
+
+        // Put the service on its own process group so we can kill all of its descendants.
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = self.spec.start_tracked(&mut cmd)?;
+        let pid = child.id();
+
+        tokio::task::spawn_local(self.spec.feed(&mut child.stdin));
+        tokio::task::spawn_local(self.spec.relay(&mut child.stdout, &mut child.stderr));
+
+        Ok(Running(child, pid))
+    }
+
+    /// Returns exit status of the process and how it was ended.
+    async fn supervise(
+        &self,
+        mut prog: Running,
+        i: usize,
+        state: &State,
+        interrupt: &Interrupt,
+    ) -> (Option<ExitStatus>, End) {
+        let pid = prog.1;
+
+        if self.health.is_none() {
+            state.set(i, Phase::Running);
+        }
+
+        // Wait for the process.
+        let monitor = async {
+            match &self.health {
+                Some(v) => v.monitor(i, state).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let (sig, end) = tokio::select! {
+            v = prog.0.wait() => return (v.ok(), End::Exited),
+            _ = monitor => (TERM, End::Unhealthy),
+            _ = state.stopped() => (TERM, End::Stopped),
+            e = interrupt.wait() => (e.signal(), End::Stopped),
+        };
+
+        // Terminate the process.
+        if send(Some(&mut prog.0), pid, true, Some(sig)).is_err() {
+            return (None, end);
+        }
+
+        (reap(&mut prog.0, pid, true, GRACE).await.ok(), end)
+    }
+}
+
+/// Running process of a service.
+///
+/// The whole process group will be killed when this is dropped.
+struct Running(TrackedChild, Option<u32>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Ok(None) = self.0.try_wait() {
+            send(Some(&mut self.0), self.1, true, None).ok();
+        }
+    }
+}
+
+/// How the process of a service was ended.
+#[derive(Clone, Copy)]
+enum End {
+    /// The process exited by itself.
+    Exited,
+    /// The process was terminated since it failed the health check.
+    Unhealthy,
+    /// The process was terminated since the services were stopped or Project was interrupted.
+    Stopped,
+}
+
+/// Restart policy of a service.
+#[derive(Clone, Copy)]
+enum Restart {
+    No,
+    OnFailure,
+    Always,
+}
+
+impl FromStr for Restart {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no" => Ok(Self::No),
+            "on-failure" => Ok(Self::OnFailure),
+            "always" => Ok(Self::Always),
+            v => Err(format!("invalid option '{v}'").into()),
+        }
+    }
+}
+
+/// Health check of a service.
+struct Health {
+    check: Check,
+    interval: Duration,
+    threshold: u32,
+    start_period: Duration,
+}
+
+impl Health {
+    fn from_table(
+        cx: &Context<App, Args>,
+        arg: &Arg<App>,
+        name: &str,
+        t: &Table<App>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // Get check.
+        let check = if t.len() != 0 {
+            let mut spec = Spec::from_table(cx, arg, t)?;
+
+            spec.stdout = Output::Null;
+            spec.stderr = Output::Null;
+
+            Check::Command(Box::new(spec))
+        } else {
+            let host = match t.get_str_key("host") {
+                Value::Nil => "127.0.0.1".to_owned(),
+                Value::Str(v) => v
+                    .as_utf8()
+                    .ok_or_else(|| {
+                        arg.error(format!("expect UTF-8 string on '{name}.health.host'"))
+                    })?
+                    .to_owned(),
+                v => {
+                    return Err(arg.error(format!(
+                        "expect string on '{name}.health.host', got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            let port = match t.get_str_key("port") {
+                Value::Int(v) => u16::try_from(v).map_err(|_| {
+                    arg.error(format!("value out of range on '{name}.health.port'"))
+                })?,
+                v => {
+                    return Err(arg.error(format!(
+                        "expect integer on '{name}.health.port', got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            Check::Port(host, port)
+        };
+
+        // Get interval.
+        let interval = get_duration(
+            cx,
+            arg,
+            &format!("{name}.health.interval"),
+            t.get_str_key("interval"),
+        )?
+        .unwrap_or(Duration::from_secs(1));
+
+        // Get threshold.
+        let threshold = match t.get_str_key("threshold") {
+            Value::Nil => 3,
+            Value::Int(v) => u32::try_from(v).ok().filter(|&v| v != 0).ok_or_else(|| {
+                arg.error(format!("value out of range on '{name}.health.threshold'"))
+            })?,
+            v => {
+                return Err(arg.error(format!(
+                    "expect integer on '{name}.health.threshold', got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        // Get start period.
+        let start_period = get_duration(
+            cx,
+            arg,
+            &format!("{name}.health.start_period"),
+            t.get_str_key("start_period"),
+        )?
+        .unwrap_or(Duration::ZERO);
+
+        Ok(Self {
+            check,
+            interval,
+            threshold,
+            start_period,
+        })
+    }
+
+    /// Returns when the service become unhealthy.
+    async fn monitor(&self, i: usize, state: &State) {
+        let started = Instant::now();
+        let mut healthy = false;
+        let mut failed = 0;
+
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            // Check.
+            let ok = match &self.check {
+                Check::Port(h, p) => {
+                    let conn = tokio::net::TcpStream::connect((h.as_str(), *p));
+
+                    matches!(tokio::time::timeout(self.interval, conn).await, Ok(Ok(_)))
+                }
+                Check::Command(spec) => match spec.spawn() {
+                    Ok(mut v) => {
+                        let r = tokio::time::timeout(self.interval, v.wait()).await;
+
+                        matches!(r, Ok(Ok(v)) if v.success())
+                    }
+                    Err(_) => false,
+                },
+            };
+
+            // Failed checks before the service become healthy are only ignored within the start
+            // period otherwise we will wait forever for the service that never become healthy.
+            if ok {
+                failed = 0;
+
+                if !std::mem::replace(&mut healthy, true) {
+                    state.set(i, Phase::Healthy);
+                }
+            } else if healthy || started.elapsed() >= self.start_period {
+                failed += 1;
+
+                if failed == self.threshold {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Type of health check.
+enum Check {
+    Port(String, u16),
+    Command(Box<Spec>),
+}
+
+/// Maximum delay before restarting a service.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Minimum running time for a service to reset its backoff.
+const RESET: Duration = Duration::from_secs(60);
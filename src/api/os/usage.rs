@@ -0,0 +1,249 @@
+use super::create_status;
+use crate::App;
+use std::process::ExitStatus;
+use std::time::Duration;
+#[cfg(not(unix))]
+use tokio::process::Child;
+use tokio::process::{ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::time::Instant;
+use tsuki::context::Context;
+use tsuki::{Ref, Table};
+
+/// Child process that collect [Usage] when it is reaped.
+///
+/// The process must be reaped via [Self::wait()] or [Self::try_wait()] for [Usage] to be
+/// available. On Unix the process will be reaped by `wait4` so the resource usage is from the same
+/// call that reap the process. We can't use [Child] in this case since it does not know the process
+/// has been reaped so dropping it will kill or reap the process that reuse the same PID.
+pub struct TrackedChild {
+    #[cfg(unix)]
+    pid: u32,
+    #[cfg(not(unix))]
+    child: Child,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+    start: Instant,
+    #[cfg(unix)]
+    status: Option<ExitStatus>,
+    usage: Option<Usage>,
+}
+
+impl TrackedChild {
+    /// Spawn `cmd`. The process will be killed when this is dropped without reaping it.
+    #[cfg(unix)]
+    pub fn spawn(cmd: &mut Command) -> std::io::Result<Self> {
+        let mut child = cmd.as_std_mut().spawn()?;
+        let start = Instant::now();
+
+        // Dropping std::process::Child does not kill nor reap the process so we only need its
+        // streams.
+        Ok(Self {
+            pid: child.id(),
+            stdin: child.stdin.take().map(ChildStdin::from_std).transpose()?,
+            stdout: child.stdout.take().map(ChildStdout::from_std).transpose()?,
+            stderr: child.stderr.take().map(ChildStderr::from_std).transpose()?,
+            start,
+            status: None,
+            usage: None,
+        })
+    }
+
+    /// Spawn `cmd`. The process will be killed when this is dropped without reaping it.
+    #[cfg(not(unix))]
+    pub fn spawn(cmd: &mut Command) -> std::io::Result<Self> {
+        let mut child = cmd.spawn()?;
+
+        Ok(Self {
+            stdin: child.stdin.take(),
+            stdout: child.stdout.take(),
+            stderr: child.stderr.take(),
+            child,
+            start: Instant::now(),
+            usage: None,
+        })
+    }
+
+    /// Returns [None] if the process has been reaped.
+    #[cfg(unix)]
+    pub fn id(&self) -> Option<u32> {
+        self.status.is_none().then_some(self.pid)
+    }
+
+    /// Returns [None] if the process has been reaped.
+    #[cfg(not(unix))]
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Returns [None] if the process has not been reaped.
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    #[cfg(not(unix))]
+    pub fn start_kill(&mut self) -> std::io::Result<()> {
+        self.child.start_kill()
+    }
+
+    #[cfg(unix)]
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        // We need to listen for SIGCHLD before checking otherwise we may miss it.
+        let mut sig = signal(SignalKind::child())?;
+
+        loop {
+            if let Some(v) = self.try_wait()? {
+                return Ok(v);
+            }
+
+            sig.recv().await;
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub async fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        let status = self.child.wait().await?;
+
+        if self.usage.is_none() {
+            self.usage = Some(Usage::new(&self.start, None));
+        }
+
+        Ok(status)
+    }
+
+    #[cfg(unix)]
+    pub fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(v) = self.status {
+            return Ok(Some(v));
+        }
+
+        // Reap.
+        let mut status = 0;
+        let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        let pid = self.pid.try_into().unwrap();
+        let r = unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) };
+
+        if r < 0 {
+            return Err(std::io::Error::last_os_error());
+        } else if r == 0 {
+            return Ok(None);
+        }
+
+        let status = ExitStatus::from_raw(status);
+
+        self.status = Some(status);
+        self.usage = Some(Usage::new(&self.start, Some(Rusage::new(&usage))));
+
+        Ok(Some(status))
+    }
+
+    #[cfg(not(unix))]
+    pub fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        let status = self.child.try_wait()?;
+
+        if status.is_some() && self.usage.is_none() {
+            self.usage = Some(Usage::new(&self.start, None));
+        }
+
+        Ok(status)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if !matches!(self.try_wait(), Ok(None)) {
+            return;
+        }
+
+        // Kill the process the same as Child with kill_on_drop then reap it in the background so
+        // it does not become a zombie. We can't do anything if the runtime already gone since
+        // there is no way to wait for SIGCHLD.
+        let pid: i32 = self.pid.try_into().unwrap();
+
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+
+        if let Ok(rt) = tokio::runtime::Handle::try_current() {
+            rt.spawn(async move {
+                let mut sig = match signal(SignalKind::child()) {
+                    Ok(v) => v,
+                    Err(_) => return,
+                };
+
+                while unsafe { libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG) } == 0 {
+                    sig.recv().await;
+                }
+            });
+        }
+    }
+}
+
+/// Resource usage of a process.
+pub struct Usage {
+    time: Duration,
+    rusage: Option<Rusage>,
+}
+
+impl Usage {
+    fn new(start: &Instant, rusage: Option<Rusage>) -> Self {
+        Self {
+            time: start.elapsed(),
+            rusage,
+        }
+    }
+
+    /// Create a table contains `status` and all resource usage of the process.
+    pub fn create_table<'a, T>(
+        &self,
+        cx: &Context<'a, App, T>,
+        status: ExitStatus,
+    ) -> Ref<'a, Table<App>> {
+        let t = create_status(cx, status);
+
+        t.set_str_key("time", self.time.as_secs_f64());
+
+        if let Some(v) = &self.rusage {
+            t.set_str_key("user", v.user.as_secs_f64());
+            t.set_str_key("system", v.system.as_secs_f64());
+            t.set_str_key("rss", i64::try_from(v.rss).unwrap_or(i64::MAX));
+        }
+
+        t
+    }
+}
+
+/// CPU time and peak memory of a process, including all of its descendants that has been waited.
+#[cfg_attr(not(unix), allow(dead_code))]
+struct Rusage {
+    user: Duration,
+    system: Duration,
+    rss: u64,
+}
+
+impl Rusage {
+    #[cfg(unix)]
+    fn new(usage: &libc::rusage) -> Self {
+        let time = |v: libc::timeval| {
+            Duration::from_secs(v.tv_sec as u64) + Duration::from_micros(v.tv_usec as u64)
+        };
+
+        // The peak memory is in bytes on macOS and kilobytes on the others.
+        let rss = usage.ru_maxrss as u64;
+        let rss = match cfg!(target_os = "macos") {
+            true => rss,
+            false => rss * 1024,
+        };
+
+        Self {
+            user: time(usage.ru_utime),
+            system: time(usage.ru_stime),
+            rss,
+        }
+    }
+}
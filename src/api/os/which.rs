@@ -0,0 +1,115 @@
+use crate::App;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tsuki::Nil;
+use tsuki::context::{Args, Context, Ret};
+
+/// Implementation of `os.which`.
+pub fn entry(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get arguments.
+    let name = cx.arg(1);
+    let name = name
+        .to_str()?
+        .as_utf8()
+        .ok_or_else(|| name.error("expect UTF-8 string"))?;
+    let path = cx.arg(2);
+    let path = match path.to_nilable_str(false)? {
+        Some(v) => Some(
+            v.as_utf8()
+                .ok_or_else(|| path.error("expect UTF-8 string"))?
+                .to_owned(),
+        ),
+        None => None,
+    };
+
+    // Search.
+    match find(name, path.as_deref().map(OsStr::new)) {
+        Some(v) => cx.push_str(v)?,
+        None => cx.push(Nil)?,
+    }
+
+    Ok(cx.into())
+}
+
+/// Returns absolute path of the executable `name` by searching `path` or `PATH` if `path` is
+/// [None].
+///
+/// If `name` contains a directory it will be checked directly without searching. Non-UTF-8 paths
+/// are considered not found.
+pub fn find(name: &str, path: Option<&OsStr>) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+
+    // Check if name contains a directory.
+    let name = Path::new(name);
+
+    if name.components().count() > 1 || name.is_absolute() {
+        return resolve(name);
+    }
+
+    // Search.
+    let path = match path {
+        Some(v) => v.to_owned(),
+        None => std::env::var_os("PATH")?,
+    };
+
+    std::env::split_paths(&path).find_map(|d| {
+        // An empty entry means the current directory.
+        let d = match d.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => d,
+        };
+
+        resolve(&d.join(name))
+    })
+}
+
+#[cfg(unix)]
+fn resolve(path: &Path) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let meta = std::fs::metadata(path).ok()?;
+
+    if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+        return None;
+    }
+
+    std::path::absolute(path)
+        .ok()
+        .and_then(|v| v.into_os_string().into_string().ok())
+}
+
+#[cfg(windows)]
+fn resolve(path: &Path) -> Option<String> {
+    // Try the path as-is if it already has an extension in PATHEXT (e.g. cargo.exe).
+    let exts = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+    let exts = exts.split(';').filter(|v| !v.is_empty());
+    let has = |e: &OsStr| {
+        let e = e.to_str().unwrap_or_default();
+
+        exts.clone()
+            .any(|v| v.trim_start_matches('.').eq_ignore_ascii_case(e))
+    };
+
+    let found = match path.extension() {
+        Some(e) if has(e) => Some(path.to_owned()),
+        _ => None,
+    }
+    .filter(|v| v.is_file())
+    .or_else(|| {
+        exts.clone().find_map(|e| {
+            let mut v = path.as_os_str().to_owned();
+
+            v.push(e);
+
+            let v = PathBuf::from(v);
+
+            v.is_file().then_some(v)
+        })
+    })?;
+
+    std::path::absolute(found)
+        .ok()
+        .and_then(|v| v.into_os_string().into_string().ok())
+}
@@ -0,0 +1,237 @@
+use super::Exit;
+use crate::App;
+use std::cell::RefCell;
+use std::mem::transmute;
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tsuki::context::{Arg, Args, Context, Ret};
+use tsuki::{CallError, Lua, LuaFn, Module, Nil, Ref, Table, Thread, Value, fp};
+
+/// Implementation of [Module] for `task` API.
+pub struct TaskModule;
+
+impl Module<App> for TaskModule {
+    const NAME: &str = "task";
+
+    type Inst<'a> = Ref<'a, Table<App>>;
+
+    fn open(self, lua: &Lua<App>) -> Result<Self::Inst<'_>, Box<dyn core::error::Error>> {
+        let m = lua.create_table();
+
+        m.set_str_key("all", fp!(all as async));
+        m.set_str_key("join", fp!(join as async));
+        m.set_str_key("race", fp!(race as async));
+        m.set_str_key("spawn", fp!(spawn));
+
+        Ok(m)
+    }
+}
+
+fn spawn(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    // Get function and its arguments.
+    let f = cx.arg(1);
+    let f = match Value::from_arg(&f) {
+        Some(Value::LuaFn(v)) => v,
+        _ => return Err(f.invalid_type("Lua function")),
+    };
+    let mut args = Vec::with_capacity(cx.args().saturating_sub(1));
+
+    for i in 2..=cx.args() {
+        args.push(Value::from_arg(&cx.arg(i)).unwrap());
+    }
+
+    // Spawn. The task may never be joined so we need to forward exit to the main thread ourselves.
+    // The interrupt is already delivered to all threads.
+    let interrupt = cx.associated_data().interrupt.clone();
+    let task = start(cx.create_thread(), f, args);
+    let task = tokio::task::spawn_local(async move {
+        let r = task.await;
+
+        if let Err(e) = &r {
+            let e = match e.downcast_ref::<CallError>() {
+                Some(v) => v.reason(),
+                None => e.as_ref(),
+            };
+
+            if let Some(v) = e.downcast_ref::<Exit>() {
+                interrupt.raise_exit(v.code());
+            }
+        }
+
+        r
+    });
+
+    cx.push(cx.create_ud(Task(RefCell::new(Some(task)))))?;
+
+    Ok(cx.into())
+}
+
+async fn join(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    let arg = cx.arg(1);
+    let interrupt = &cx.associated_data().interrupt;
+
+    if let Some(t) = arg.as_table() {
+        for i in 1..=t.len() {
+            // Get task.
+            let task = match t.get(i) {
+                Value::UserData(v) => v,
+                v => {
+                    return Err(arg.error(format!(
+                        "expect task at index #{i}, got {}",
+                        cx.type_name(v)
+                    )));
+                }
+            };
+
+            let task = task
+                .downcast::<Task>()
+                .ok_or_else(|| arg.error(format!("expect task at index #{i}")))?;
+
+            // Wait for result.
+            let r = interrupt.guard(task.value().wait()).await??;
+
+            match r.into_iter().next() {
+                Some(v) => cx.push(v)?,
+                None => cx.push(Nil)?,
+            }
+        }
+    } else {
+        let task = arg.get_ud::<Task>()?;
+
+        for v in interrupt.guard(task.value().wait()).await?? {
+            cx.push(v)?;
+        }
+    }
+
+    Ok(cx.into())
+}
+
+async fn all(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    // Spawn tasks.
+    let arg = cx.arg(1);
+    let mut tasks = spawn_all(&cx, &arg)?;
+
+    // Wait for all tasks. The remaining tasks will be aborted when JoinSet is dropped.
+    let mut results = Vec::with_capacity(tasks.len());
+
+    results.resize_with(tasks.len(), || None);
+
+    let interrupt = &cx.associated_data().interrupt;
+
+    while let Some(r) = interrupt.guard(tasks.join_next()).await? {
+        let (i, r) = r.map_err(join_error)?;
+
+        results[i] = r?.into_iter().next();
+    }
+
+    for v in results {
+        match v {
+            Some(v) => cx.push(v)?,
+            None => cx.push(Nil)?,
+        }
+    }
+
+    Ok(cx.into())
+}
+
+async fn race(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    // Spawn tasks.
+    let arg = cx.arg(1);
+    let mut tasks = spawn_all(&cx, &arg)?;
+
+    // Wait for the first task. The remaining tasks will be aborted when JoinSet is dropped.
+    let interrupt = &cx.associated_data().interrupt;
+    let (i, r) = match interrupt.guard(tasks.join_next()).await? {
+        Some(v) => v.map_err(join_error)?,
+        None => return Err(arg.error("expect non-empty table")),
+    };
+
+    cx.push(i64::try_from(i + 1).unwrap())?;
+
+    for v in r? {
+        cx.push(v)?;
+    }
+
+    Ok(cx.into())
+}
+
+fn spawn_all(
+    cx: &Context<App, Args>,
+    arg: &Arg<App>,
+) -> Result<JoinSet<(usize, Output)>, Box<dyn std::error::Error>> {
+    let t = arg.get_table()?;
+    let mut tasks = JoinSet::new();
+
+    for i in 1..=t.len() {
+        let f = match t.get(i) {
+            Value::LuaFn(v) => v,
+            v => {
+                return Err(arg.error(format!(
+                    "expect function at index #{i}, got {}",
+                    cx.type_name(v)
+                )));
+            }
+        };
+
+        let task = start(cx.create_thread(), f, Vec::new());
+        let i = usize::try_from(i - 1).unwrap();
+
+        tasks.spawn_local(async move { (i, task.await) });
+    }
+
+    Ok(tasks)
+}
+
+fn start<'a>(
+    td: Ref<'a, Thread<App>>,
+    f: Ref<'a, LuaFn<App>>,
+    args: Vec<Value<'a, App>>,
+) -> impl Future<Output = Output> + 'static {
+    // SAFETY: The LocalSet that run the task borrow Lua (see Tasks in main.rs) so the task will
+    // never outlive Lua.
+    let td = unsafe { transmute::<Ref<'a, Thread<App>>, Ref<'static, Thread<App>>>(td) };
+    let f = unsafe { transmute::<Ref<'a, LuaFn<App>>, Ref<'static, LuaFn<App>>>(f) };
+    let args = unsafe { transmute::<Vec<Value<'a, App>>, Vec<Value<'static, App>>>(args) };
+
+    async move {
+        let r = td.async_call::<Vec<Value<App>>>(&f, args).await?;
+
+        // SAFETY: Same as above.
+        Ok(unsafe { transmute::<Vec<Value<'_, App>>, Vec<Value<'static, App>>>(r) })
+    }
+}
+
+fn join_error(e: JoinError) -> Box<dyn std::error::Error> {
+    match e.try_into_panic() {
+        Ok(v) => std::panic::resume_unwind(v),
+        Err(_) => "the task was cancelled".into(),
+    }
+}
+
+/// Class of the value returned from `task.spawn`.
+struct Task(RefCell<Option<JoinHandle<Output>>>);
+
+impl Task {
+    async fn wait(&self) -> Output {
+        let mut task = self
+            .0
+            .try_borrow_mut()
+            .map_err(|_| "the task is being joined by another thread")?;
+        let r = match task.as_mut() {
+            Some(v) => v.await,
+            None => return Err("the task already joined".into()),
+        };
+
+        *task = None;
+
+        r.map_err(join_error)?
+    }
+}
+
+/// Results of a task.
+type Output = Result<Vec<Value<'static, App>>, Box<dyn std::error::Error>>;
@@ -0,0 +1,94 @@
+use crate::App;
+use std::time::Duration;
+use tsuki::context::{Arg, Args, Context, Ret};
+use tsuki::{Lua, Module, Ref, Table, Value, fp};
+
+/// Implementation of [Module] for `time` API.
+pub struct TimeModule;
+
+impl Module<App> for TimeModule {
+    const NAME: &str = "time";
+
+    type Inst<'a> = Ref<'a, Table<App>>;
+
+    fn open(self, lua: &Lua<App>) -> Result<Self::Inst<'_>, Box<dyn core::error::Error>> {
+        let m = lua.create_table();
+
+        m.set_str_key("elapsed", fp!(elapsed));
+        m.set_str_key("now", fp!(now));
+        m.set_str_key("sleep", fp!(sleep as async));
+        m.set_str_key("timeout", fp!(timeout as async));
+
+        Ok(m)
+    }
+}
+
+fn elapsed(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    let start = f64::from(cx.arg(1).to_float()?);
+    let now = cx.associated_data().start.elapsed().as_secs_f64();
+
+    cx.push(now - start)?;
+
+    Ok(cx.into())
+}
+
+fn now(cx: Context<App, Args>) -> Result<Context<App, Ret>, Box<dyn std::error::Error>> {
+    let now = cx.associated_data().start.elapsed().as_secs_f64();
+
+    cx.push(now)?;
+
+    Ok(cx.into())
+}
+
+async fn sleep(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    let dur = to_duration(&cx.arg(1))?;
+
+    cx.associated_data()
+        .interrupt
+        .guard(tokio::time::sleep(dur))
+        .await?;
+
+    Ok(cx.into())
+}
+
+async fn timeout(
+    cx: Context<'_, App, Args>,
+) -> Result<Context<'_, App, Ret>, Box<dyn std::error::Error>> {
+    // Get arguments.
+    let dur = to_duration(&cx.arg(1))?;
+    let f = cx.arg(2);
+    let f = f
+        .as_lua_fn()
+        .ok_or_else(|| f.invalid_type("Lua function"))?;
+    let mut args = Vec::with_capacity(cx.args().saturating_sub(2));
+
+    for i in 3..=cx.args() {
+        args.push(Value::from_arg(&cx.arg(i)).unwrap());
+    }
+
+    // Invoke the function on a new thread so we can drop it when timed out.
+    let td = cx.create_thread();
+    let r = match tokio::time::timeout(dur, td.async_call::<Vec<Value<App>>>(f, args)).await {
+        Ok(v) => v?,
+        Err(_) => {
+            cx.push(false)?;
+            return Ok(cx.into());
+        }
+    };
+
+    cx.push(true)?;
+
+    for v in r {
+        cx.push(v)?;
+    }
+
+    Ok(cx.into())
+}
+
+fn to_duration(arg: &Arg<App>) -> Result<Duration, Box<dyn std::error::Error>> {
+    let secs = f64::from(arg.to_float()?);
+
+    Duration::try_from_secs_f64(secs).map_err(|e| arg.error(e))
+}
@@ -2,21 +2,24 @@
 #![allow(clippy::new_ret_no_self)] // We need this for Lua userdata.
 
 use self::api::{
-    ArgsModule, GlobalModule, JsonModule, OsModule, PathModule, StringModule, UrlModule,
+    ArgsModule, DateModule, GlobalModule, Interrupt, JsonModule, OsModule, PathModule, Reaper,
+    StringModule, TaskModule, TimeModule, UrlModule,
 };
 use self::manifest::{ArgName, ArgType, CommandArg, Project, ScriptPath};
 use clap::builder::PossibleValuesParser;
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use erdp::ErrorDisplay;
 use rustc_hash::FxHashMap;
+use std::error::Error;
 use std::fs::File;
+use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
-use std::pin::Pin;
 use std::process::{ExitCode, Termination};
 use std::rc::Rc;
+use std::time::Instant;
 use tokio::task::LocalSet;
 use tsuki::builtin::{CoroLib, IoLib, MathLib, TableLib, Utf8Lib};
-use tsuki::{CallError, Lua, ParseError};
+use tsuki::{CallError, Lua, ParseError, Value};
 
 mod api;
 mod manifest;
@@ -105,13 +108,33 @@ fn main() -> Exit {
 }
 
 fn run_script(script: ScriptPath, defs: FxHashMap<ArgName, CommandArg>, args: ArgMatches) -> Exit {
+    // Get positional arguments in the same order as command line.
+    let mut positionals = Vec::new();
+
+    for (id, def) in &defs {
+        if def.long.is_some() || def.short.is_some() || !matches!(def.ty, ArgType::String) {
+            continue;
+        }
+
+        if let (Some(i), Some(v)) = (args.index_of(id), args.get_one::<String>(id)) {
+            positionals.push((i, v.clone()));
+        }
+    }
+
+    positionals.sort_unstable_by_key(|v| v.0);
+
     // Register modules.
-    let lua = Lua::new(App {});
+    let lua = Lua::new(App {
+        start: Instant::now(),
+        reaper: Rc::default(),
+        interrupt: Rc::default(),
+    });
 
     lua.use_module(None, true, ArgsModule { defs, args })
         .unwrap();
     lua.use_module(None, true, GlobalModule).unwrap();
     lua.use_module(None, true, CoroLib).unwrap();
+    lua.use_module(None, true, DateModule).unwrap();
     lua.use_module(None, true, IoLib).unwrap();
     lua.use_module(None, true, JsonModule).unwrap();
     lua.use_module(None, true, MathLib).unwrap();
@@ -119,6 +142,8 @@ fn run_script(script: ScriptPath, defs: FxHashMap<ArgName, CommandArg>, args: Ar
     lua.use_module(None, true, PathModule).unwrap();
     lua.use_module(None, true, StringModule).unwrap();
     lua.use_module(None, true, TableLib).unwrap();
+    lua.use_module(None, true, TaskModule).unwrap();
+    lua.use_module(None, true, TimeModule).unwrap();
     lua.use_module(None, true, UrlModule).unwrap();
     lua.use_module(None, true, Utf8Lib).unwrap();
 
@@ -132,12 +157,35 @@ fn run_script(script: ScriptPath, defs: FxHashMap<ArgName, CommandArg>, args: Ar
     };
 
     // Execute the script.
-    let local = LocalSet::new();
+    let tasks = Tasks::new(&lua);
+    let args = positionals.into_iter().map(|v| v.1).collect();
+    let r = tasks.0.block_on(&tokio, async {
+        let app = lua.associated_data();
+
+        // Handle interrupt signals.
+        match Signals::new() {
+            Ok(v) => tokio::task::spawn_local(handle_signals(app.interrupt.clone(), v)),
+            Err(e) => return Exit::SetupSignal(e),
+        };
+
+        let r = exec_script(&lua, script, args).await;
 
-    local.block_on(&tokio, exec_script(lua, script))
+        // Give the processes that are being terminated a chance to exit gracefully.
+        app.reaper.join().await;
+
+        match app.interrupt.signal() {
+            Some(_) => Exit::Interrupted,
+            None => r,
+        }
+    });
+
+    // The tasks spawned by the script may hold Lua objects so we need to drop it before Lua.
+    drop(tasks);
+
+    r
 }
 
-async fn exec_script(lua: Pin<Rc<Lua<App>>>, script: ScriptPath) -> Exit {
+async fn exec_script(lua: &Lua<App>, script: ScriptPath, args: Vec<String>) -> Exit {
     // Read script.
     let chunk = match std::fs::read(&script) {
         Ok(v) => v,
@@ -152,15 +200,141 @@ async fn exec_script(lua: Pin<Rc<Lua<App>>>, script: ScriptPath) -> Exit {
 
     // Run the script.
     let td = lua.create_thread();
+    let args = args
+        .into_iter()
+        .map(|v| Value::Str(lua.create_str(v)))
+        .collect::<Vec<_>>();
+    let r = td.async_call::<Vec<Value<App>>>(&chunk, args).await;
+
+    // The script may finish without reaching the await that raise exit from a detached task.
+    if let Some(v) = lua.associated_data().interrupt.exited() {
+        return Exit::ScriptResult(v);
+    }
+
+    let r = match r {
+        Ok(v) => v,
+        Err(e) => return Exit::RunScript(script, e),
+    };
+
+    // Get exit code.
+    match r.first() {
+        None | Some(Value::Nil | Value::True) => Exit::ScriptResult(0),
+        Some(Value::False) => match r.get(1) {
+            Some(Value::Str(v)) => {
+                Exit::ScriptFailed(Some(String::from_utf8_lossy(v.as_bytes()).into_owned()))
+            }
+            _ => Exit::ScriptFailed(None),
+        },
+        Some(Value::Int(v)) => match u8::try_from(*v).ok().filter(|c| matches!(c, 0..=99)) {
+            Some(v) => Exit::ScriptResult(v),
+            None => Exit::RunScript(script, format!("exit code {v} is out of range").into()),
+        },
+        Some(Value::Float(v)) => {
+            // Accept a float with exact integer value the same as exit.
+            let v = f64::from(*v);
+
+            if v.fract() != 0.0 || !v.is_finite() {
+                let e = format!("exit code {v} has no integer representation");
+
+                Exit::RunScript(script, e.into())
+            } else if (0.0..=99.0).contains(&v) {
+                Exit::ScriptResult(v as u8)
+            } else {
+                Exit::RunScript(script, format!("exit code {v} is out of range").into())
+            }
+        }
+        Some(v) => {
+            let e = format!("expect number or boolean as a result, got {}", v.ty());
+
+            Exit::RunScript(script, e.into())
+        }
+    }
+}
+
+/// Forward the first signal to the script and the child processes then exit immediately on the
+/// second signal.
+async fn handle_signals(interrupt: Rc<Interrupt>, mut signals: Signals) {
+    interrupt.raise(signals.recv().await);
+
+    signals.recv().await;
+
+    // There is no stable way to exit with ExitCode so we need to duplicate Exit::Interrupted here.
+    eprintln!("Interrupted.");
+    std::process::exit(107);
+}
+
+/// [LocalSet] to run the tasks spawned by the script.
+///
+/// The tasks may hold Lua objects with `'static` lifetime so this borrow [Lua] to make sure it
+/// can't be dropped before the tasks.
+struct Tasks<'a>(LocalSet, PhantomData<&'a Lua<App>>);
+
+impl<'a> Tasks<'a> {
+    fn new(_: &'a Lua<App>) -> Self {
+        Self(LocalSet::new(), PhantomData)
+    }
+}
 
-    match td.async_call(&chunk, ()).await {
-        Ok(()) => Exit::ScriptResult(0),
-        Err(e) => Exit::RunScript(script, e),
+impl Drop for Tasks<'_> {
+    fn drop(&mut self) {
+        // Having Drop implementation require the borrow to be alive until this is dropped.
     }
 }
 
 /// Associated data of [Lua].
-struct App {}
+struct App {
+    start: Instant,
+    reaper: Rc<Reaper>,
+    interrupt: Rc<Interrupt>,
+}
+
+/// Signals to interrupt the script.
+#[cfg(unix)]
+struct Signals {
+    int: tokio::signal::unix::Signal,
+    term: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Result<Self, std::io::Error> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        // The runtime may be blocked (e.g. lines() of the process output) so we need to record
+        // the signal directly from the handler too.
+        for sig in [libc::SIGINT, libc::SIGTERM] {
+            self::api::record_signal(sig)?;
+        }
+
+        Ok(Self {
+            int: signal(SignalKind::interrupt())?,
+            term: signal(SignalKind::terminate())?,
+        })
+    }
+
+    async fn recv(&mut self) -> i32 {
+        tokio::select! {
+            _ = self.int.recv() => libc::SIGINT,
+            _ = self.term.recv() => libc::SIGTERM,
+        }
+    }
+}
+
+/// Signals to interrupt the script.
+#[cfg(windows)]
+struct Signals(tokio::signal::windows::CtrlC);
+
+#[cfg(windows)]
+impl Signals {
+    fn new() -> Result<Self, std::io::Error> {
+        tokio::signal::windows::ctrl_c().map(Self)
+    }
+
+    async fn recv(&mut self) -> i32 {
+        self.0.recv().await;
+        2
+    }
+}
 
 /// Action of a command.
 enum CommandAction {
@@ -171,12 +345,15 @@ enum CommandAction {
 #[repr(u8)]
 enum Exit {
     ScriptResult(u8),
+    ScriptFailed(Option<String>) = 1,
     RunScript(ScriptPath, Box<dyn std::error::Error>) = 100,
     OpenProject(PathBuf, std::io::Error) = 102, // 101 is Rust panic.
     LoadProject(PathBuf, serde_yaml::Error) = 103,
     NoCommandAction(String) = 104,
     ReadScript(ScriptPath, std::io::Error) = 105,
     LoadScript(ScriptPath, ParseError) = 106,
+    Interrupted = 107,
+    SetupSignal(std::io::Error) = 108,
     SetupTokio(std::io::Error) = 109,
 }
 
@@ -188,16 +365,20 @@ impl Termination for Exit {
 
         match self {
             Self::ScriptResult(v) => code = v,
+            Self::ScriptFailed(m) => {
+                if let Some(m) = m {
+                    eprintln!("{m}");
+                }
+            }
             Self::RunScript(p, e) => match e.downcast::<CallError>() {
                 Ok(e) => match e.reason().downcast_ref::<self::api::Exit>() {
                     Some(e) => code = e.code(),
-                    None => {
-                        let (f, l) = e.location().unwrap();
-
-                        eprintln!("{}:{}: {}.", f, l, e.display());
-                    }
+                    None => report_call_error(&e),
+                },
+                Err(e) => match e.downcast_ref::<self::api::Exit>() {
+                    Some(e) => code = e.code(),
+                    None => eprintln!("Failed to run {}: {}.", p, e.display()),
                 },
-                Err(e) => eprintln!("Failed to run {}: {}.", p, e.display()),
             },
             Self::OpenProject(p, e) => {
                 eprintln!("Failed to open {}: {}.", p.display(), e.display())
@@ -210,9 +391,38 @@ impl Termination for Exit {
                 eprintln!("Failed to read {}: {}.", p, e.display())
             }
             Self::LoadScript(p, e) => eprintln!("{}:{}: {}.", p, e.line(), e.display()),
+            Self::Interrupted => eprintln!("Interrupted."),
+            Self::SetupSignal(e) => eprintln!("Failed to setup signal handler: {}.", e.display()),
             Self::SetupTokio(e) => eprintln!("Failed to setup Tokio: {}.", e.display()),
         }
 
         code.into()
     }
 }
+
+/// Print `e` to stderr with each nested error on its own line.
+///
+/// Nested [CallError] will also have its location printed so the error raised from the other Lua
+/// thread can be traced back. This is the closest we can get to a traceback since tsuki only keep
+/// the chunk name and line of the innermost Lua frame in [CallError]. The name of the function is
+/// not recorded and there is no API to walk the stack before it is unwound so we can't print it.
+fn report_call_error(e: &CallError) {
+    match e.location() {
+        Some((f, l)) => eprintln!("{f}:{l}: {e}."),
+        None => eprintln!("{e}."),
+    }
+
+    let mut next = e.source();
+
+    while let Some(e) = next {
+        match e.downcast_ref::<CallError>() {
+            Some(e) => match e.location() {
+                Some((f, l)) => eprintln!("  at {f}:{l}: {e}."),
+                None => eprintln!("  at <unknown>: {e}."),
+            },
+            None => eprintln!("  caused by: {e}."),
+        }
+
+        next = e.source();
+    }
+}
@@ -0,0 +1,80 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn restart_unhealthy_service_that_exit_successfully() {
+    // The service exit with zero code on SIGTERM, which must not prevent it from restarting when it
+    // was terminated by the health check.
+    let out = run(
+        "restart-unhealthy",
+        r#"
+local services <close> = os.supervise({
+  svc = {
+    'sh', '-c', 'trap "exit 0" TERM; while true; do sleep 0.1; done',
+    retries = 1,
+    backoff = 0.1,
+    health = {'false', interval = 0.1, threshold = 2},
+  },
+})
+
+local name, code = services:wait()
+
+print(name, code, services:status()[name])
+"#,
+    );
+
+    assert_eq!(out, "svc\t0\tfailed\n");
+}
+
+#[test]
+fn ignore_failed_health_checks_within_start_period() {
+    let out = run(
+        "start-period",
+        r#"
+local services <close> = os.supervise({
+  svc = {
+    'sh', '-c', 'rm -f ready; sleep 0.5; touch ready; sleep 30',
+    retries = 0,
+    health = {'test', '-f', 'ready', interval = 0.1, threshold = 2, start_period = 5},
+  },
+})
+
+services:ready()
+
+print(services:status().svc)
+"#,
+    );
+
+    assert_eq!(out, "healthy\n");
+}
+
+/// Run `script` as a command of a new project and returns its stdout.
+fn run(name: &str, script: &str) -> String {
+    // Setup project.
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Project.yml"),
+        "commands:\n  test:\n    description: Test\n    script: test.lua\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("test.lua"), script).unwrap();
+
+    // Run.
+    let out = Command::new(env!("CARGO_BIN_EXE_project"))
+        .arg("test")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    String::from_utf8(out.stdout).unwrap()
+}